@@ -0,0 +1,337 @@
+use std::{
+    f64::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    effects::Biquad,
+    instrument::{Note, Patch, VoiceTrigger},
+    random::Random,
+    wave::Wave,
+    waves::ADSRTrigger,
+};
+
+/// Level an exponential decay has to fall below before a drum counts as finished, -80 dB.
+const SILENCE: f64 = 1e-4;
+
+/// A sine whose pitch falls from `start` to `end` (both in Hz) while it fades out. `sweep` and
+/// `decay` are the times in seconds the pitch and the volume take to cover ~63% of their way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub start: f64,
+    pub end: f64,
+    pub sweep: f64,
+    pub decay: f64,
+    pub level: f64,
+}
+
+impl Tone {
+    pub fn new(start: f64, end: f64, sweep: f64, decay: f64, level: f64) -> Self {
+        Self {
+            start,
+            end,
+            sweep,
+            decay,
+            level,
+        }
+    }
+}
+
+/// Filtered white noise that fades out within `decay` seconds (to ~37%). Claps restart the burst
+/// `repeats` times, `spacing` seconds apart, before the last one fades out.
+#[derive(Debug, Clone)]
+pub struct NoiseBurst {
+    pub filter: Biquad,
+    pub decay: f64,
+    pub level: f64,
+    pub repeats: usize,
+    pub spacing: f64,
+}
+
+impl NoiseBurst {
+    pub fn new(filter: Biquad, decay: f64, level: f64) -> Self {
+        Self {
+            filter,
+            decay,
+            level,
+            repeats: 0,
+            spacing: 0.0,
+        }
+    }
+
+    pub fn with_repeats(mut self, repeats: usize, spacing: f64) -> Self {
+        self.repeats = repeats;
+        self.spacing = spacing;
+        self
+    }
+
+    /// When the last burst starts, in seconds.
+    fn last_start(&self) -> f64 {
+        self.repeats as f64 * self.spacing
+    }
+}
+
+/// A synthesized drum sound, made of pitched tones and a noise burst.
+#[derive(Debug, Clone, Default)]
+pub struct Drum {
+    pub tones: Vec<Tone>,
+    pub noise: Option<NoiseBurst>,
+}
+
+impl Drum {
+    pub fn new(tones: Vec<Tone>, noise: Option<NoiseBurst>) -> Self {
+        Self { tones, noise }
+    }
+
+    pub fn kick() -> Self {
+        Self::new(
+            vec![Tone::new(160.0, 48.0, 0.03, 0.25, 1.0)],
+            Some(NoiseBurst::new(Biquad::lowpass(3000.0, 0.7), 0.004, 0.3)),
+        )
+    }
+
+    pub fn snare() -> Self {
+        Self::new(
+            vec![Tone::new(320.0, 180.0, 0.02, 0.08, 0.5)],
+            Some(NoiseBurst::new(Biquad::bandpass(3500.0, 0.6), 0.09, 0.8)),
+        )
+    }
+
+    pub fn rimshot() -> Self {
+        Self::new(
+            vec![Tone::new(1700.0, 1600.0, 0.01, 0.012, 0.7)],
+            Some(NoiseBurst::new(Biquad::highpass(2500.0, 0.7), 0.008, 0.4)),
+        )
+    }
+
+    pub fn clap() -> Self {
+        Self::new(
+            Vec::new(),
+            Some(NoiseBurst::new(Biquad::bandpass(1200.0, 1.2), 0.06, 1.0).with_repeats(3, 0.011)),
+        )
+    }
+
+    /// A tom tuned to `frequency`.
+    pub fn tom(frequency: f64) -> Self {
+        Self::new(
+            vec![Tone::new(frequency * 1.6, frequency, 0.04, 0.3, 0.9)],
+            Some(NoiseBurst::new(Biquad::lowpass(2000.0, 0.7), 0.01, 0.15)),
+        )
+    }
+
+    pub fn closed_hihat() -> Self {
+        Self::new(
+            Vec::new(),
+            Some(NoiseBurst::new(Biquad::highpass(7000.0, 0.7), 0.02, 0.6)),
+        )
+    }
+
+    pub fn pedal_hihat() -> Self {
+        Self::new(
+            Vec::new(),
+            Some(NoiseBurst::new(Biquad::highpass(6000.0, 0.7), 0.035, 0.4)),
+        )
+    }
+
+    pub fn open_hihat() -> Self {
+        Self::new(
+            Vec::new(),
+            Some(NoiseBurst::new(Biquad::highpass(7000.0, 0.7), 0.25, 0.5)),
+        )
+    }
+
+    pub fn crash() -> Self {
+        Self::new(
+            Vec::new(),
+            Some(NoiseBurst::new(Biquad::highpass(4000.0, 0.5), 0.8, 0.6)),
+        )
+    }
+
+    pub fn ride() -> Self {
+        Self::new(
+            vec![Tone::new(3200.0, 3200.0, 1.0, 0.5, 0.08)],
+            Some(NoiseBurst::new(Biquad::bandpass(8000.0, 0.8), 0.6, 0.35)),
+        )
+    }
+
+    pub fn cowbell() -> Self {
+        Self::new(
+            vec![
+                Tone::new(587.0, 587.0, 1.0, 0.12, 0.5),
+                Tone::new(845.0, 845.0, 1.0, 0.12, 0.5),
+            ],
+            None,
+        )
+    }
+
+    /// Seconds until all layers have faded out.
+    fn length(&self) -> f64 {
+        let fade = -SILENCE.ln();
+        let tones = self.tones.iter().map(|tone| tone.decay * fade);
+        let noise = self
+            .noise
+            .iter()
+            .map(|noise| noise.last_start() + noise.decay * fade);
+        tones.chain(noise).fold(0.0, f64::max)
+    }
+}
+
+/// One hit of a [`Drum`]. Drums always play until they have faded out, releasing the key has no
+/// effect.
+pub struct DrumVoice {
+    drum: Drum,
+    phases: Vec<f64>,
+    time: f64,
+    length: f64,
+    level: f64,
+    random: Random,
+    idle: Arc<AtomicBool>,
+}
+
+impl DrumVoice {
+    pub fn new(drum: Drum, velocity: u8) -> (Self, ADSRTrigger) {
+        let idle = Arc::new(AtomicBool::new(false));
+        let voice = Self {
+            phases: vec![0.0; drum.tones.len()],
+            time: 0.0,
+            length: drum.length(),
+            level: velocity as f64 / 127.0,
+            random: Random::new(),
+            idle: idle.clone(),
+            drum,
+        };
+        // Presses and releases are ignored, the trigger only reports when the drum has finished.
+        (voice, ADSRTrigger::new(Arc::new(AtomicU32::new(0)), idle))
+    }
+}
+
+impl Wave for DrumVoice {
+    fn next_sample(&mut self) -> f64 {
+        if self.time >= self.length {
+            self.idle.store(true, Ordering::Relaxed);
+            return 0.0;
+        }
+
+        let time = self.time;
+        let mut out = 0.0;
+
+        for (tone, phase) in self.drum.tones.iter().zip(self.phases.iter_mut()) {
+            let frequency = tone.end + (tone.start - tone.end) * (-time / tone.sweep).exp();
+            *phase = (*phase + frequency / 44100.0) % 1.0;
+            out += (*phase * TAU).sin() * tone.level * (-time / tone.decay).exp();
+        }
+
+        if let Some(noise) = &mut self.drum.noise {
+            // Time since the latest burst started.
+            let since = if time < noise.last_start() {
+                time % noise.spacing
+            } else {
+                time - noise.last_start()
+            };
+            let x = noise.filter.process(self.random.bipolar());
+            out += x * noise.level * (-since / noise.decay).exp();
+        }
+
+        self.time += 1.0 / 44100.0;
+        out * self.level
+    }
+}
+
+/// Maps keys to drums. Keys without a drum stay silent.
+///
+/// ```
+/// use rust_audio_shenanigans::drums::{Drum, DrumKit};
+/// use rust_audio_shenanigans::instrument::PolyInstrument;
+///
+/// // A General MIDI kit with a deeper kick.
+/// let mut kick = Drum::kick();
+/// kick.tones[0].end = 40.0;
+/// let kit = DrumKit::general_midi().with_drum(36, kick);
+/// let (mut drums, wave) = PolyInstrument::new(kit);
+/// ```
+#[derive(Clone)]
+pub struct DrumKit {
+    drums: Vec<Option<Drum>>,
+}
+
+impl DrumKit {
+    /// An empty kit.
+    pub fn new() -> Self {
+        Self {
+            drums: vec![None; 128],
+        }
+    }
+
+    /// A kit that covers the common sounds of the General MIDI percussion map, as played on
+    /// channel 10 of MIDI files.
+    pub fn general_midi() -> Self {
+        let toms = [
+            (41, 80.0),
+            (43, 100.0),
+            (45, 125.0),
+            (47, 150.0),
+            (48, 180.0),
+            (50, 215.0),
+        ];
+
+        let mut kit = Self::new()
+            .with_drum(35, Drum::kick())
+            .with_drum(36, Drum::kick())
+            .with_drum(37, Drum::rimshot())
+            .with_drum(38, Drum::snare())
+            .with_drum(39, Drum::clap())
+            .with_drum(40, Drum::snare())
+            .with_drum(42, Drum::closed_hihat())
+            .with_drum(44, Drum::pedal_hihat())
+            .with_drum(46, Drum::open_hihat())
+            .with_drum(49, Drum::crash())
+            .with_drum(51, Drum::ride())
+            .with_drum(52, Drum::crash())
+            .with_drum(53, Drum::ride())
+            .with_drum(55, Drum::crash())
+            .with_drum(56, Drum::cowbell())
+            .with_drum(57, Drum::crash())
+            .with_drum(59, Drum::ride());
+        for (key, frequency) in toms {
+            kit = kit.with_drum(key, Drum::tom(frequency));
+        }
+        kit
+    }
+
+    pub fn with_drum(mut self, key: u8, drum: Drum) -> Self {
+        self.set_drum(key, Some(drum));
+        self
+    }
+
+    pub fn set_drum(&mut self, key: u8, drum: Option<Drum>) {
+        if let Some(slot) = self.drums.get_mut(key as usize) {
+            *slot = drum;
+        }
+    }
+
+    pub fn drum(&self, key: u8) -> Option<&Drum> {
+        self.drums.get(key as usize)?.as_ref()
+    }
+}
+
+impl Default for DrumKit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Patch for DrumKit {
+    type Voice = DrumVoice;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        let drum = self
+            .drum(note.key.min(127) as u8)
+            .cloned()
+            .unwrap_or_default();
+        let (voice, trigger) = DrumVoice::new(drum, note.velocity);
+        (voice, trigger.into())
+    }
+}
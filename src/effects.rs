@@ -4,6 +4,60 @@ use crate::{
     wave::{Wave, WaveGenerator},
 };
 
+mod biquad;
+mod dynamics;
+mod eq;
+mod modulation;
+mod pitch;
+mod utility;
+mod vocoder;
+
+pub use biquad::Biquad;
+
+pub use dynamics::{
+    compressor, envelope_follower, expander, limiter, noise_gate, Compression, Compressor,
+    Dynamics, EnvelopeFollower, Expander, Expansion, GainCurve, Key, Limiter, NoiseGate,
+    PartialDynamics, PartialEnvelopeFollower, PartialLimiter, PartialNoiseGate, SelfKey, Sidechain,
+};
+
+pub use eq::{equalizer, Band, BandKind, Equalizer, PartialEqualizer, Slope};
+pub use modulation::{
+    auto_pan, ring_modulator, synced, tremolo, AutoPan, PartialAutoPan, PartialRingModulator,
+    PartialTremolo, RingModulator, Tremolo,
+};
+pub use pitch::{pitch_shift, time_stretch, PartialPitchShifter, PitchShifter};
+pub use utility::{
+    dc_blocker, distortion, oversampled_shaper, DcBlocker, Distortion, OversampledShaper,
+    Oversampling, PartialDcBlocker, PartialDistortion, PartialOversampledShaper,
+};
+pub use vocoder::{vocoder, PartialVocoder, Vocoder};
+
+pub fn db_to_gain(db: f64) -> f64 {
+    10.0f64.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f64) -> f64 {
+    20.0 * gain.abs().log10()
+}
+
+/// Equal-power gains for the left and right channel at `position` between -1.0 (left) and 1.0
+/// (right). A centered signal ends up 3 dB quieter on each side.
+pub fn pan_gains(position: f64) -> (f64, f64) {
+    let angle = (position.clamp(-1.0, 1.0) + 1.0) * std::f64::consts::FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// Coefficient of a one-pole smoother that covers ~63% of a step in `time` seconds.
+pub(crate) fn time_coefficient(time: f64) -> f64 {
+    if time > 0.0 {
+        (-1.0 / (time * 44100.0)).exp()
+    } else {
+        0.0
+    }
+}
+
+/// Resonant lowpass. Left and right channel are filtered separately, `next_sample` runs through
+/// the left one.
 #[derive(Clone)]
 pub struct Lowpass<T> {
     a1: f64,
@@ -11,8 +65,8 @@ pub struct Lowpass<T> {
     a3: f64,
     b1: f64,
     b2: f64,
-    in_buffer: [f64; 2],
-    out_buffer: [f64; 2],
+    in_buffer: [[f64; 2]; 2],
+    out_buffer: [[f64; 2]; 2],
     offset: usize,
     input: T,
 }
@@ -27,8 +81,8 @@ impl<T> Lowpass<T> {
         let b1 = 2.0 * (1.0 - c * c) * a1;
         let b2 = (1.0 - r * c + c * c) * a1;
 
-        let in_buffer = [0.0; 2];
-        let out_buffer = [0.0; 2];
+        let in_buffer = [[0.0; 2]; 2];
+        let out_buffer = [[0.0; 2]; 2];
 
         Self {
             a1,
@@ -43,35 +97,51 @@ impl<T> Lowpass<T> {
         }
         .into()
     }
-}
 
-impl<W> Wave for Lowpass<W>
-where
-    W: Wave,
-{
+    /// Filter the next sample of `channel` (0 is left, 1 is right), without moving on.
     #[inline]
-    fn next_sample(&mut self) -> f64 {
+    fn filter(&mut self, channel: usize, in0: f64) -> f64 {
         let i1 = self.offset;
         let i2 = 1 - self.offset;
+        let in_buffer = &mut self.in_buffer[channel];
+        let out_buffer = &mut self.out_buffer[channel];
 
-        let in0 = self.input.next_sample();
-        let in1 = self.in_buffer[i1];
-        let in2 = self.in_buffer[i2];
-        self.in_buffer[i2] = in0;
+        let in1 = in_buffer[i1];
+        let in2 = in_buffer[i2];
+        in_buffer[i2] = in0;
 
-        let out1 = self.out_buffer[i1];
-        let out2 = self.out_buffer[i2];
+        let out1 = out_buffer[i1];
+        let out2 = out_buffer[i2];
 
         let out = self.a1 * in0 + self.a2 * in1 + self.a3 * in2 - self.b1 * out1 - self.b2 * out2;
 
-        self.out_buffer[i2] = out;
-
-        self.offset = i2;
+        out_buffer[i2] = out;
 
         out * 4.0
     }
 }
 
+impl<W> Wave for Lowpass<W>
+where
+    W: Wave,
+{
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let in0 = self.input.next_sample();
+        let out = self.filter(0, in0);
+        self.offset = 1 - self.offset;
+        out
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        let out = (self.filter(0, l), self.filter(1, r));
+        self.offset = 1 - self.offset;
+        out
+    }
+}
+
 make_partial!(
     PartialLowpass {
         f: f64,
@@ -0,0 +1,161 @@
+use std::f64::consts::{PI, TAU};
+
+/// Second order IIR filter section in transposed direct form II. The coefficient formulas are the
+/// ones from Robert Bristow-Johnson's Audio EQ Cookbook, see
+/// https://www.w3.org/TR/audio-eq-cookbook/
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+/// Intermediate values shared by all cookbook formulas.
+struct Prototype {
+    cos: f64,
+    alpha: f64,
+}
+
+impl Prototype {
+    fn new(frequency: f64, q: f64) -> Self {
+        let w0 = TAU * frequency.clamp(1.0, 22049.0) / 44100.0;
+        Self {
+            cos: w0.cos(),
+            alpha: w0.sin() / (2.0 * q),
+        }
+    }
+}
+
+impl Biquad {
+    /// Filter that lets everything pass unchanged.
+    pub fn identity() -> Self {
+        Self::from_coefficients(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    pub fn from_coefficients(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        let mut biquad = Self {
+            b0: 0.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            z1: 0.0,
+            z2: 0.0,
+        };
+        biquad.set_coefficients(b0, b1, b2, a0, a1, a2);
+        biquad
+    }
+
+    /// Replace the coefficients while keeping the filter state, so parameters can change while a
+    /// signal is running through the filter.
+    pub fn set_coefficients(&mut self, b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) {
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+
+    pub fn set(&mut self, other: &Biquad) {
+        self.b0 = other.b0;
+        self.b1 = other.b1;
+        self.b2 = other.b2;
+        self.a1 = other.a1;
+        self.a2 = other.a2;
+    }
+
+    pub fn lowpass(frequency: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        Self::from_coefficients(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn highpass(frequency: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        Self::from_coefficients(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn bandpass(frequency: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        Self::from_coefficients(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    /// `gain` is given in dB.
+    pub fn peaking(frequency: f64, gain: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        let a = 10.0f64.powf(gain / 40.0);
+        Self::from_coefficients(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    /// `gain` is given in dB.
+    pub fn low_shelf(frequency: f64, gain: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        let a = 10.0f64.powf(gain / 40.0);
+        let sq = 2.0 * a.sqrt() * alpha;
+        Self::from_coefficients(
+            a * ((a + 1.0) - (a - 1.0) * cos + sq),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - sq),
+            (a + 1.0) + (a - 1.0) * cos + sq,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - sq,
+        )
+    }
+
+    /// `gain` is given in dB.
+    pub fn high_shelf(frequency: f64, gain: f64, q: f64) -> Self {
+        let Prototype { cos, alpha } = Prototype::new(frequency, q);
+        let a = 10.0f64.powf(gain / 40.0);
+        let sq = 2.0 * a.sqrt() * alpha;
+        Self::from_coefficients(
+            a * ((a + 1.0) + (a - 1.0) * cos + sq),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - sq),
+            (a + 1.0) - (a - 1.0) * cos + sq,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - sq,
+        )
+    }
+
+    /// Q of the `section`th biquad in a Butterworth filter of the given (even) `order`.
+    pub fn butterworth_q(order: usize, section: usize) -> f64 {
+        1.0 / (2.0 * (PI * (2 * section + 1) as f64 / (2 * order) as f64).cos())
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    #[inline]
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
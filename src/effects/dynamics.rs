@@ -0,0 +1,587 @@
+use std::collections::VecDeque;
+
+use crate::{
+    effects::{db_to_gain, gain_to_db, time_coefficient},
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+};
+
+/// How long the level detector of the noise gate holds on to a peak, so that it doesn't chatter
+/// on every zero crossing of the key signal.
+const GATE_DETECTOR_RELEASE: f64 = 0.005;
+
+#[derive(Clone)]
+pub(crate) struct PeakDetector {
+    attack: f64,
+    release: f64,
+    envelope: f64,
+}
+
+impl PeakDetector {
+    pub(crate) fn new(attack: f64, release: f64) -> Self {
+        Self {
+            attack: time_coefficient(attack),
+            release: time_coefficient(release),
+            envelope: 0.0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn process(&mut self, x: f64) -> f64 {
+        let x = x.abs();
+        let coefficient = if x > self.envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope = x + coefficient * (self.envelope - x);
+        self.envelope
+    }
+}
+
+/// Follows the peak amplitude of its input. Attack and release are given in seconds.
+#[derive(Clone)]
+pub struct EnvelopeFollower<T> {
+    detector: PeakDetector,
+    input: T,
+}
+
+impl<T> EnvelopeFollower<T> {
+    pub fn new(attack: f64, release: f64, input: T) -> WaveGenerator<Self> {
+        Self {
+            detector: PeakDetector::new(attack, release),
+            input,
+        }
+        .into()
+    }
+}
+
+impl<W: Wave> Wave for EnvelopeFollower<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        self.detector.process(self.input.next_sample())
+    }
+}
+
+make_partial!(PartialEnvelopeFollower { attack: f64, release: f64 } => EnvelopeFollower);
+
+pub fn envelope_follower(attack: f64, release: f64) -> PartialWaveBuilder<PartialEnvelopeFollower> {
+    PartialEnvelopeFollower::new(attack, release)
+}
+
+/// The signal that drives the level detector of a dynamics processor.
+pub trait Key {
+    fn key(&mut self, input: f64) -> f64;
+}
+
+/// Detect the level of the processed signal itself.
+#[derive(Clone)]
+pub struct SelfKey;
+
+impl Key for SelfKey {
+    #[inline]
+    fn key(&mut self, input: f64) -> f64 {
+        input
+    }
+}
+
+/// Detect the level of another wave, e.g. to duck a pad whenever the kick drum hits.
+#[derive(Clone)]
+pub struct Sidechain<W> {
+    key: W,
+}
+
+impl<W: Wave> Key for Sidechain<W> {
+    #[inline]
+    fn key(&mut self, _input: f64) -> f64 {
+        self.key.next_sample()
+    }
+}
+
+/// Static curve of a dynamics processor. Maps the detected level to a gain change, both in dB.
+pub trait GainCurve {
+    fn gain(&self, level: f64) -> f64;
+}
+
+/// Downward compression above `threshold` dB, with a soft knee that is `knee` dB wide.
+#[derive(Clone)]
+pub struct Compression {
+    pub threshold: f64,
+    pub ratio: f64,
+    pub knee: f64,
+}
+
+impl GainCurve for Compression {
+    #[inline]
+    fn gain(&self, level: f64) -> f64 {
+        let over = level - self.threshold;
+        let slope = 1.0 / self.ratio - 1.0;
+
+        if 2.0 * over <= -self.knee {
+            0.0
+        } else if 2.0 * over < self.knee {
+            slope * (over + self.knee / 2.0).powi(2) / (2.0 * self.knee)
+        } else {
+            slope * over
+        }
+    }
+}
+
+/// Downward expansion below `threshold` dB. The attenuation never exceeds `range` dB.
+#[derive(Clone)]
+pub struct Expansion {
+    pub threshold: f64,
+    pub ratio: f64,
+    pub knee: f64,
+    pub range: f64,
+}
+
+impl GainCurve for Expansion {
+    #[inline]
+    fn gain(&self, level: f64) -> f64 {
+        let under = level - self.threshold;
+        let slope = self.ratio - 1.0;
+
+        let gain = if 2.0 * under >= self.knee {
+            0.0
+        } else if 2.0 * under > -self.knee {
+            -slope * (under - self.knee / 2.0).powi(2) / (2.0 * self.knee)
+        } else {
+            slope * under
+        };
+
+        gain.max(-self.range)
+    }
+}
+
+/// Feed-forward dynamics processor. The level of the key signal is tracked with a peak detector,
+/// mapped through the gain curve `C` and applied to the input, followed by `makeup` gain. Stereo
+/// input is detected from the louder channel and both channels get the same gain, so the image
+/// doesn't shift.
+#[derive(Clone)]
+pub struct Dynamics<C, T, K> {
+    curve: C,
+    detector: PeakDetector,
+    makeup: f64,
+    key: K,
+    input: T,
+}
+
+pub type Compressor<T, K = SelfKey> = Dynamics<Compression, T, K>;
+pub type Expander<T, K = SelfKey> = Dynamics<Expansion, T, K>;
+
+impl<C, T, K> Dynamics<C, T, K> {
+    pub fn new(
+        curve: C,
+        attack: f64,
+        release: f64,
+        makeup: f64,
+        key: K,
+        input: T,
+    ) -> WaveGenerator<Self> {
+        Self {
+            curve,
+            detector: PeakDetector::new(attack, release),
+            makeup: db_to_gain(makeup),
+            key,
+            input,
+        }
+        .into()
+    }
+}
+
+impl<C, T, K> Dynamics<C, T, K>
+where
+    C: GainCurve,
+    K: Key,
+{
+    /// Gain for an input sample with the given peak value, including makeup.
+    #[inline]
+    fn gain(&mut self, peak: f64) -> f64 {
+        let level = self.detector.process(self.key.key(peak));
+        let gain = self.curve.gain(gain_to_db(level));
+
+        db_to_gain(gain) * self.makeup
+    }
+}
+
+impl<C, W, K> Wave for Dynamics<C, W, K>
+where
+    C: GainCurve,
+    W: Wave,
+    K: Key,
+{
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        x * self.gain(x)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        let gain = self.gain(l.abs().max(r.abs()));
+        (l * gain, r * gain)
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialDynamics<C, K> {
+    curve: C,
+    attack: f64,
+    release: f64,
+    makeup: f64,
+    key: K,
+}
+
+impl<C, K> PartialWave for PartialDynamics<C, K>
+where
+    C: GainCurve + Clone + Send + Sync,
+    K: Key + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = Dynamics<C, W, K>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        Dynamics::new(
+            self.curve,
+            self.attack,
+            self.release,
+            self.makeup,
+            self.key,
+            input,
+        )
+    }
+}
+
+impl<C> PartialWaveBuilder<PartialDynamics<C, SelfKey>> {
+    /// Drive the level detector from `key` instead of the processed signal.
+    pub fn sidechain<W>(
+        self,
+        key: WaveGenerator<W>,
+    ) -> PartialWaveBuilder<PartialDynamics<C, Sidechain<W>>> {
+        let partial = self.into_inner();
+        PartialWaveBuilder::new(PartialDynamics {
+            curve: partial.curve,
+            attack: partial.attack,
+            release: partial.release,
+            makeup: partial.makeup,
+            key: Sidechain { key: key.source },
+        })
+    }
+}
+
+/// Feed-forward compressor. `threshold`, `knee` and `makeup` are in dB, `attack` and `release` in
+/// seconds.
+pub fn compressor(
+    threshold: f64,
+    ratio: f64,
+    knee: f64,
+    attack: f64,
+    release: f64,
+    makeup: f64,
+) -> PartialWaveBuilder<PartialDynamics<Compression, SelfKey>> {
+    PartialWaveBuilder::new(PartialDynamics {
+        curve: Compression {
+            threshold,
+            ratio,
+            knee,
+        },
+        attack,
+        release,
+        makeup,
+        key: SelfKey,
+    })
+}
+
+/// Downward expander. `threshold`, `knee` and `range` are in dB, `attack` and `release` in
+/// seconds.
+pub fn expander(
+    threshold: f64,
+    ratio: f64,
+    knee: f64,
+    range: f64,
+    attack: f64,
+    release: f64,
+) -> PartialWaveBuilder<PartialDynamics<Expansion, SelfKey>> {
+    PartialWaveBuilder::new(PartialDynamics {
+        curve: Expansion {
+            threshold,
+            ratio,
+            knee,
+            range,
+        },
+        attack,
+        release,
+        makeup: 0.0,
+        key: SelfKey,
+    })
+}
+
+/// Noise gate. Opens as soon as the key signal rises above `threshold` dB, stays open for `hold`
+/// seconds after it falls below again and then closes, attenuating by `range` dB. `attack` and
+/// `release` are the times it takes to fully open and close. Both channels of a stereo input are
+/// gated together.
+#[derive(Clone)]
+pub struct NoiseGate<T, K> {
+    threshold: f64,
+    floor: f64,
+    open_step: f64,
+    close_step: f64,
+    hold: usize,
+    hold_counter: usize,
+    gain: f64,
+    detector: PeakDetector,
+    key: K,
+    input: T,
+}
+
+impl<T, K> NoiseGate<T, K> {
+    pub fn new(
+        threshold: f64,
+        range: f64,
+        attack: f64,
+        hold: f64,
+        release: f64,
+        key: K,
+        input: T,
+    ) -> WaveGenerator<Self> {
+        let step = |time: f64| {
+            if time > 0.0 {
+                1.0 / (time * 44100.0)
+            } else {
+                1.0
+            }
+        };
+
+        Self {
+            threshold: db_to_gain(threshold),
+            floor: db_to_gain(-range),
+            open_step: step(attack),
+            close_step: step(release),
+            hold: (hold * 44100.0) as usize,
+            hold_counter: 0,
+            gain: db_to_gain(-range),
+            detector: PeakDetector::new(0.0, GATE_DETECTOR_RELEASE),
+            key,
+            input,
+        }
+        .into()
+    }
+}
+
+impl<T, K: Key> NoiseGate<T, K> {
+    /// Open or close the gate a step further for an input sample with the given peak value.
+    #[inline]
+    fn gain(&mut self, peak: f64) -> f64 {
+        let level = self.detector.process(self.key.key(peak));
+
+        let open = if level >= self.threshold {
+            self.hold_counter = self.hold;
+            true
+        } else if self.hold_counter > 0 {
+            self.hold_counter -= 1;
+            true
+        } else {
+            false
+        };
+
+        self.gain = if open {
+            (self.gain + self.open_step).min(1.0)
+        } else {
+            (self.gain - self.close_step).max(self.floor)
+        };
+
+        self.gain
+    }
+}
+
+impl<W, K> Wave for NoiseGate<W, K>
+where
+    W: Wave,
+    K: Key,
+{
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        x * self.gain(x)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        let gain = self.gain(l.abs().max(r.abs()));
+        (l * gain, r * gain)
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialNoiseGate<K> {
+    threshold: f64,
+    range: f64,
+    attack: f64,
+    hold: f64,
+    release: f64,
+    key: K,
+}
+
+impl<K> PartialWave for PartialNoiseGate<K>
+where
+    K: Key + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = NoiseGate<W, K>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        NoiseGate::new(
+            self.threshold,
+            self.range,
+            self.attack,
+            self.hold,
+            self.release,
+            self.key,
+            input,
+        )
+    }
+}
+
+impl PartialWaveBuilder<PartialNoiseGate<SelfKey>> {
+    /// Open and close the gate based on `key` instead of the processed signal.
+    pub fn sidechain<W>(
+        self,
+        key: WaveGenerator<W>,
+    ) -> PartialWaveBuilder<PartialNoiseGate<Sidechain<W>>> {
+        let partial = self.into_inner();
+        PartialWaveBuilder::new(PartialNoiseGate {
+            threshold: partial.threshold,
+            range: partial.range,
+            attack: partial.attack,
+            hold: partial.hold,
+            release: partial.release,
+            key: Sidechain { key: key.source },
+        })
+    }
+}
+
+pub fn noise_gate(
+    threshold: f64,
+    range: f64,
+    attack: f64,
+    hold: f64,
+    release: f64,
+) -> PartialWaveBuilder<PartialNoiseGate<SelfKey>> {
+    PartialWaveBuilder::new(PartialNoiseGate {
+        threshold,
+        range,
+        attack,
+        hold,
+        release,
+        key: SelfKey,
+    })
+}
+
+/// Lookahead brickwall limiter. The output never exceeds `ceiling` dB; the input is delayed by
+/// `lookahead` seconds so the gain can ramp down before a peak arrives instead of clipping it.
+/// After a peak the gain recovers within roughly `release` seconds.
+///
+/// The gain needed by every incoming sample goes through a sliding minimum and then through a
+/// moving average, both as long as the lookahead. That way the averaged gain is already low enough
+/// by the time the peak leaves the delay line. Stereo input is limited on the louder channel, with
+/// the same gain on both.
+#[derive(Clone)]
+pub struct Limiter<T> {
+    ceiling: f64,
+    release: f64,
+    delay: VecDeque<(f64, f64)>,
+    minimum: VecDeque<(usize, f64)>,
+    average: VecDeque<f64>,
+    average_sum: f64,
+    recovered: f64,
+    position: usize,
+    input: T,
+}
+
+impl<T> Limiter<T> {
+    pub fn new(ceiling: f64, lookahead: f64, release: f64, input: T) -> WaveGenerator<Self> {
+        let window = ((lookahead * 44100.0) as usize).max(1);
+
+        Self {
+            ceiling: db_to_gain(ceiling),
+            release: time_coefficient(release),
+            delay: VecDeque::from(vec![(0.0, 0.0); window - 1]),
+            minimum: VecDeque::with_capacity(window),
+            average: VecDeque::from(vec![1.0; window]),
+            average_sum: window as f64,
+            recovered: 1.0,
+            position: 0,
+            input,
+        }
+        .into()
+    }
+
+    fn window(&self) -> usize {
+        self.average.len()
+    }
+
+    /// Push a frame into the delay line and get the one leaving it, limited.
+    #[inline]
+    fn process(&mut self, l: f64, r: f64) -> (f64, f64) {
+        let needed = (self.ceiling / l.abs().max(r.abs())).min(1.0);
+
+        // Sliding minimum over the lookahead window.
+        while matches!(self.minimum.back(), Some((_, g)) if *g >= needed) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.position, needed));
+        while matches!(self.minimum.front(), Some((i, _)) if i + self.window() <= self.position) {
+            self.minimum.pop_front();
+        }
+        let held = self.minimum.front().map_or(1.0, |(_, g)| *g);
+        self.position += 1;
+
+        // Drop instantly, recover smoothly.
+        self.recovered = if held < self.recovered {
+            held
+        } else {
+            held + self.release * (self.recovered - held)
+        };
+
+        // Moving average, so the gain reduction is ramped in over the lookahead time.
+        self.average_sum += self.recovered - self.average.pop_front().unwrap_or(1.0);
+        self.average.push_back(self.recovered);
+        let gain = self.average_sum / self.window() as f64;
+
+        self.delay.push_back((l, r));
+        let (l, r) = self.delay.pop_front().unwrap_or((0.0, 0.0));
+
+        (
+            (l * gain).clamp(-self.ceiling, self.ceiling),
+            (r * gain).clamp(-self.ceiling, self.ceiling),
+        )
+    }
+}
+
+impl<W: Wave> Wave for Limiter<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        self.process(x, x).0
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        self.process(l, r)
+    }
+}
+
+make_partial!(PartialLimiter { ceiling: f64, lookahead: f64, release: f64 } => Limiter);
+
+pub fn limiter(ceiling: f64, lookahead: f64, release: f64) -> PartialWaveBuilder<PartialLimiter> {
+    PartialLimiter::new(ceiling, lookahead, release)
+}
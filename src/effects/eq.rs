@@ -0,0 +1,201 @@
+use crate::{
+    effects::Biquad,
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+    Variable,
+};
+
+/// Steepness of a high or low cut, built from cascaded Butterworth sections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slope {
+    Db12,
+    Db24,
+    Db36,
+    Db48,
+}
+
+impl Slope {
+    fn order(self) -> usize {
+        match self {
+            Slope::Db12 => 2,
+            Slope::Db24 => 4,
+            Slope::Db36 => 6,
+            Slope::Db48 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowCut(Slope),
+    HighCut(Slope),
+}
+
+/// Parameters of a single EQ band. `gain` is given in dB and ignored by the cut filters, which
+/// use Butterworth alignment and ignore `q` as well.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: BandKind,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+    pub enabled: bool,
+}
+
+impl Band {
+    pub fn peaking(frequency: f64, gain: f64, q: f64) -> Self {
+        Self::new(BandKind::Peaking, frequency, gain, q)
+    }
+
+    pub fn low_shelf(frequency: f64, gain: f64) -> Self {
+        Self::new(
+            BandKind::LowShelf,
+            frequency,
+            gain,
+            std::f64::consts::FRAC_1_SQRT_2,
+        )
+    }
+
+    pub fn high_shelf(frequency: f64, gain: f64) -> Self {
+        Self::new(
+            BandKind::HighShelf,
+            frequency,
+            gain,
+            std::f64::consts::FRAC_1_SQRT_2,
+        )
+    }
+
+    pub fn low_cut(frequency: f64, slope: Slope) -> Self {
+        Self::new(BandKind::LowCut(slope), frequency, 0.0, 0.0)
+    }
+
+    pub fn high_cut(frequency: f64, slope: Slope) -> Self {
+        Self::new(BandKind::HighCut(slope), frequency, 0.0, 0.0)
+    }
+
+    fn new(kind: BandKind, frequency: f64, gain: f64, q: f64) -> Self {
+        Self {
+            kind,
+            frequency,
+            gain,
+            q,
+            enabled: true,
+        }
+    }
+
+    fn sections(&self) -> Vec<Biquad> {
+        if !self.enabled {
+            return vec![];
+        }
+
+        match self.kind {
+            BandKind::Peaking => vec![Biquad::peaking(self.frequency, self.gain, self.q)],
+            BandKind::LowShelf => vec![Biquad::low_shelf(self.frequency, self.gain, self.q)],
+            BandKind::HighShelf => vec![Biquad::high_shelf(self.frequency, self.gain, self.q)],
+            BandKind::LowCut(slope) => (0..slope.order() / 2)
+                .map(|i| Biquad::butterworth_q(slope.order(), i))
+                .map(|q| Biquad::highpass(self.frequency, q))
+                .collect(),
+            BandKind::HighCut(slope) => (0..slope.order() / 2)
+                .map(|i| Biquad::butterworth_q(slope.order(), i))
+                .map(|q| Biquad::lowpass(self.frequency, q))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct EqBand {
+    params: Variable<Band>,
+    current: Band,
+    /// Filter sections of the left and right channel.
+    sections: [Vec<Biquad>; 2],
+}
+
+impl EqBand {
+    fn new(params: Variable<Band>) -> Self {
+        let current = *params.value();
+        Self {
+            params,
+            current,
+            sections: [current.sections(), current.sections()],
+        }
+    }
+
+    /// Pick up parameter changes. The filter state is kept as long as the number of sections
+    /// stays the same, so sweeping a band doesn't click.
+    fn update(&mut self) {
+        let params = *self.params.update();
+        if params == self.current {
+            return;
+        }
+
+        let sections = params.sections();
+        for channel in &mut self.sections {
+            if sections.len() == channel.len() {
+                for (section, new) in channel.iter_mut().zip(sections.iter()) {
+                    section.set(new);
+                }
+            } else {
+                *channel = sections.clone();
+            }
+        }
+        self.current = params;
+    }
+
+    /// Filter the next sample of `channel` (0 is left, 1 is right).
+    #[inline]
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        self.sections[channel]
+            .iter_mut()
+            .fold(x, |acc, section| section.process(acc))
+    }
+}
+
+/// Parametric equalizer with any number of bands. Every band is a [`Variable`], so its
+/// parameters can be changed from another thread while the equalizer is playing.
+#[derive(Clone)]
+pub struct Equalizer<T> {
+    bands: Vec<EqBand>,
+    input: T,
+}
+
+impl<T> Equalizer<T> {
+    pub fn new(bands: Vec<Variable<Band>>, input: T) -> WaveGenerator<Self> {
+        Self {
+            bands: bands.into_iter().map(EqBand::new).collect(),
+            input,
+        }
+        .into()
+    }
+}
+
+impl<W: Wave> Wave for Equalizer<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        self.bands.iter_mut().fold(x, |acc, band| {
+            band.update();
+            band.process(0, acc)
+        })
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let frame = self.input.next_frame();
+        self.bands.iter_mut().fold(frame, |(l, r), band| {
+            band.update();
+            (band.process(0, l), band.process(1, r))
+        })
+    }
+}
+
+make_partial!(PartialEqualizer { bands: Vec<Variable<Band>> } => Equalizer);
+
+pub fn equalizer(bands: Vec<Variable<Band>>) -> PartialWaveBuilder<PartialEqualizer> {
+    PartialEqualizer::new(bands)
+}
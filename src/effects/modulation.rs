@@ -0,0 +1,202 @@
+use crate::{
+    effects::pan_gains,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+    waves::{Constant, MixMul, WaveMixer},
+};
+
+/// Frequency of a note that lasts `beats` beats at the given `tempo` in beats per minute, for
+/// modulation rates that follow the song. E.g. `synced(tempo, 0.5)` runs in eighth notes.
+pub fn synced<W: Wave>(
+    tempo: WaveGenerator<W>,
+    beats: f64,
+) -> WaveGenerator<WaveMixer<MixMul, W, Constant>> {
+    tempo * (1.0 / (60.0 * beats))
+}
+
+/// Multiplies the input with a carrier wave. `mix` blends between the dry (0.0) and the fully
+/// modulated (1.0) signal.
+#[derive(Clone)]
+pub struct RingModulator<T, C> {
+    mix: f64,
+    carrier: C,
+    input: T,
+}
+
+impl<T, C> RingModulator<T, C> {
+    pub fn new(mix: f64, carrier: C, input: T) -> WaveGenerator<Self> {
+        Self {
+            mix,
+            carrier,
+            input,
+        }
+        .into()
+    }
+
+    #[inline]
+    fn gain(&self, carrier: f64) -> f64 {
+        1.0 - self.mix + self.mix * carrier
+    }
+}
+
+impl<W: Wave, C: Wave> Wave for RingModulator<W, C> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let carrier = self.carrier.next_sample();
+        self.input.next_sample() * self.gain(carrier)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let carrier = self.carrier.next_sample();
+        let gain = self.gain(carrier);
+        let (l, r) = self.input.next_frame();
+        (l * gain, r * gain)
+    }
+}
+
+/// Periodically lowers the volume of the input by up to `depth` (0.0 - 1.0), following the shape
+/// of the `lfo`, which is expected to swing between -1.0 and 1.0.
+#[derive(Clone)]
+pub struct Tremolo<T, L> {
+    depth: f64,
+    lfo: L,
+    input: T,
+}
+
+impl<T, L> Tremolo<T, L> {
+    pub fn new(depth: f64, lfo: L, input: T) -> WaveGenerator<Self> {
+        Self { depth, lfo, input }.into()
+    }
+
+    #[inline]
+    fn gain(&self, lfo: f64) -> f64 {
+        1.0 - self.depth * (1.0 - lfo) / 2.0
+    }
+}
+
+impl<W: Wave, L: Wave> Wave for Tremolo<W, L> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let lfo = self.lfo.next_sample();
+        self.input.next_sample() * self.gain(lfo)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let lfo = self.lfo.next_sample();
+        let gain = self.gain(lfo);
+        let (l, r) = self.input.next_frame();
+        (l * gain, r * gain)
+    }
+}
+
+/// Moves the input between the left and the right channel, following the shape of the `lfo`.
+/// At `depth` 1.0 the signal travels all the way from one side to the other. A stereo input is
+/// balanced, every channel stays on its side and only gets louder or quieter.
+#[derive(Clone)]
+pub struct AutoPan<T, L> {
+    depth: f64,
+    lfo: L,
+    input: T,
+}
+
+impl<T, L> AutoPan<T, L> {
+    pub fn new(depth: f64, lfo: L, input: T) -> WaveGenerator<Self> {
+        Self { depth, lfo, input }.into()
+    }
+}
+
+impl<W: Wave, L: Wave> Wave for AutoPan<W, L> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (gain_l, gain_r) = pan_gains(self.depth * self.lfo.next_sample());
+        let (l, r) = self.input.next_frame();
+        (l * gain_l, r * gain_r)
+    }
+}
+
+macro_rules! make_lfo_partial {
+    ($partial_name:ident => $target:ident) => {
+        #[derive(Clone)]
+        pub struct $partial_name<L> {
+            amount: f64,
+            lfo: L,
+        }
+
+        impl<L> PartialWave for $partial_name<L>
+        where
+            L: Wave + Clone + Send + Sync,
+        {
+            type Target<W: Wave + Clone + Send + Sync> = $target<W, L>;
+
+            fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+            where
+                W: Wave + Clone + Send + Sync,
+            {
+                $target::new(self.amount, self.lfo, input)
+            }
+        }
+    };
+}
+
+make_lfo_partial!(PartialRingModulator => RingModulator);
+make_lfo_partial!(PartialTremolo => Tremolo);
+make_lfo_partial!(PartialAutoPan => AutoPan);
+
+/// Ring modulation with a carrier of the given `shape` and `frequency`, e.g.
+/// `ring_modulator(sine(), constant(30), 1.0)`.
+pub fn ring_modulator<S, F>(
+    shape: S,
+    frequency: WaveGenerator<F>,
+    mix: f64,
+) -> PartialWaveBuilder<PartialRingModulator<S::Target<F>>>
+where
+    S: PartialWave,
+    F: Wave + Clone + Send + Sync,
+{
+    PartialWaveBuilder::new(PartialRingModulator {
+        amount: mix,
+        lfo: shape.build(frequency.source).source,
+    })
+}
+
+/// Tremolo driven by an LFO of the given `shape` and `rate`, e.g.
+/// `tremolo(triangle(), synced(tempo, 0.25), 0.5)` for sixteenth note pulses at half depth.
+pub fn tremolo<S, R>(
+    shape: S,
+    rate: WaveGenerator<R>,
+    depth: f64,
+) -> PartialWaveBuilder<PartialTremolo<S::Target<R>>>
+where
+    S: PartialWave,
+    R: Wave + Clone + Send + Sync,
+{
+    PartialWaveBuilder::new(PartialTremolo {
+        amount: depth,
+        lfo: shape.build(rate.source).source,
+    })
+}
+
+/// Auto-pan driven by an LFO of the given `shape` and `rate`, e.g.
+/// `auto_pan(sine(), constant(0.5), 1.0)`.
+pub fn auto_pan<S, R>(
+    shape: S,
+    rate: WaveGenerator<R>,
+    depth: f64,
+) -> PartialWaveBuilder<PartialAutoPan<S::Target<R>>>
+where
+    S: PartialWave,
+    R: Wave + Clone + Send + Sync,
+{
+    PartialWaveBuilder::new(PartialAutoPan {
+        amount: depth,
+        lfo: shape.build(rate.source).source,
+    })
+}
@@ -0,0 +1,167 @@
+use std::f64::consts::PI;
+
+use crate::{
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+};
+
+/// Real-time pitch shifter that works on any wave, by `semitones` up or down, without changing
+/// its duration.
+///
+/// The input runs through a delay line that is read by two taps half a `window` (in seconds)
+/// apart. The taps sweep through the delay line at the shifted speed and jump back whenever they
+/// run out of it, while a crossfade hides the jump. Shorter windows react faster, longer windows
+/// sound smoother on low notes. Both channels have a delay line of their own and share the taps.
+#[derive(Clone)]
+pub struct PitchShifter<T> {
+    ratio: f64,
+    window: f64,
+    buffers: [Vec<f64>; 2],
+    position: usize,
+    phase: f64,
+    input: T,
+}
+
+impl<T> PitchShifter<T> {
+    pub fn new(semitones: f64, window: f64, input: T) -> WaveGenerator<Self> {
+        let window = (window * 44100.0).max(2.0);
+
+        Self {
+            ratio: 2.0f64.powf(semitones / 12.0),
+            window,
+            buffers: [
+                vec![0.0; window as usize + 2],
+                vec![0.0; window as usize + 2],
+            ],
+            position: 0,
+            phase: 0.0,
+            input,
+        }
+        .into()
+    }
+
+    /// Read the delay line of `channel` `delay` samples behind the write position, interpolating
+    /// linearly.
+    #[inline]
+    fn read(&self, channel: usize, delay: f64) -> f64 {
+        let buffer = &self.buffers[channel];
+        let len = buffer.len();
+        let whole = delay as usize;
+        let fraction = delay - whole as f64;
+        let a = buffer[(self.position + len - whole) % len];
+        let b = buffer[(self.position + len - whole - 1) % len];
+        a + (b - a) * fraction
+    }
+
+    /// Write the next input frame and move both taps along.
+    #[inline]
+    fn write(&mut self, l: f64, r: f64) {
+        self.position = (self.position + 1) % self.buffers[0].len();
+        self.buffers[0][self.position] = l;
+        self.buffers[1][self.position] = r;
+
+        self.phase = (self.phase + (1.0 - self.ratio) / self.window).rem_euclid(1.0);
+    }
+
+    /// Crossfaded output of both taps for `channel`.
+    #[inline]
+    fn output(&self, channel: usize) -> f64 {
+        let other = (self.phase + 0.5) % 1.0;
+
+        // sin² and cos² always add up to one, so the crossfade keeps the volume constant.
+        let fade = (PI * self.phase).sin().powi(2);
+        self.read(channel, self.phase * self.window) * fade
+            + self.read(channel, other * self.window) * (1.0 - fade)
+    }
+}
+
+impl<W: Wave> Wave for PitchShifter<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        self.write(x, x);
+        self.output(0)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        self.write(l, r);
+        (self.output(0), self.output(1))
+    }
+}
+
+make_partial!(PartialPitchShifter { semitones: f64, window: f64 } => PitchShifter);
+
+pub fn pitch_shift(semitones: f64, window: f64) -> PartialWaveBuilder<PartialPitchShifter> {
+    PartialPitchShifter::new(semitones, window)
+}
+
+/// Length of the frames `time_stretch` cuts the input into, ~46ms.
+const STRETCH_FRAME: usize = 2048;
+/// How far `time_stretch` may move a frame to find a better fit, ~5ms.
+const STRETCH_TOLERANCE: usize = 220;
+
+/// Offline time-stretch of a rendered buffer by `factor` without changing its pitch, e.g.
+/// `2.0` to make it twice as long. Render a wave with `wave.take(n).collect()` and play the
+/// result back through an `IteratorWaveSource`.
+///
+/// Uses WSOLA: windowed frames are overlap-added at a fixed output hop, and every frame is taken
+/// from the input position around its nominal one that continues the previous frame best, which
+/// avoids the phasing of plain overlap-add.
+pub fn time_stretch(samples: &[f64], factor: f64) -> Vec<f64> {
+    let hop_out = STRETCH_FRAME / 2;
+    let hop_in = hop_out as f64 / factor;
+    let output_len = (samples.len() as f64 * factor) as usize;
+
+    let window: Vec<f64> = (0..STRETCH_FRAME)
+        .map(|i| (PI * i as f64 / STRETCH_FRAME as f64).sin().powi(2))
+        .collect();
+    let at = |i: isize| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| samples.get(i))
+            .copied()
+            .unwrap_or(0.0)
+    };
+
+    let mut output = vec![0.0; output_len + STRETCH_FRAME];
+    let mut previous: Option<isize> = None;
+    let mut frame = 0;
+
+    while frame * hop_out < output_len {
+        let nominal = (frame as f64 * hop_in) as isize;
+
+        let start = match previous {
+            None => nominal,
+            Some(previous) => {
+                // The input that would have naturally followed the previous frame.
+                let natural = previous + hop_out as isize;
+                let tolerance = STRETCH_TOLERANCE as isize;
+
+                let score = |candidate: isize| {
+                    (0..hop_out as isize)
+                        .map(|i| at(candidate + i) * at(natural + i))
+                        .sum::<f64>()
+                };
+
+                (nominal - tolerance..=nominal + tolerance)
+                    .map(|candidate| (score(candidate), candidate))
+                    .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                    .map(|(_, candidate)| candidate)
+                    .unwrap_or(nominal)
+            }
+        };
+
+        for (i, w) in window.iter().enumerate() {
+            output[frame * hop_out + i] += at(start + i as isize) * w;
+        }
+
+        previous = Some(start);
+        frame += 1;
+    }
+
+    output.truncate(output_len);
+    output
+}
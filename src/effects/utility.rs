@@ -0,0 +1,228 @@
+use std::f64::consts::TAU;
+
+use crate::{
+    effects::Biquad,
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+};
+
+/// Cutoff of the DC blocker, low enough to leave the lowest notes alone.
+const DC_BLOCKER_CUTOFF: f64 = 10.0;
+
+/// Order of the Butterworth filters used to resample around an oversampled shaper.
+const OVERSAMPLING_ORDER: usize = 8;
+/// Cutoff of the resampling filters, relative to the regular sample rate.
+const OVERSAMPLING_CUTOFF: f64 = 0.45;
+
+/// Removes any constant offset from the input with a one-pole highpass at 10 Hz. Useful after
+/// waveshapers, frequency modulation or a `sawtooth()`, which swings between 0.0 and 1.0.
+#[derive(Clone)]
+pub struct DcBlocker<T> {
+    coefficient: f64,
+    /// Last input and output of the left and right channel.
+    last_in: [f64; 2],
+    last_out: [f64; 2],
+    input: T,
+}
+
+impl<T> DcBlocker<T> {
+    pub fn new(input: T) -> WaveGenerator<Self> {
+        Self {
+            coefficient: (-TAU * DC_BLOCKER_CUTOFF / 44100.0).exp(),
+            last_in: [0.0; 2],
+            last_out: [0.0; 2],
+            input,
+        }
+        .into()
+    }
+
+    #[inline]
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        self.last_out[channel] =
+            x - self.last_in[channel] + self.coefficient * self.last_out[channel];
+        self.last_in[channel] = x;
+        self.last_out[channel]
+    }
+}
+
+impl<W: Wave> Wave for DcBlocker<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        self.process(0, x)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        (self.process(0, l), self.process(1, r))
+    }
+}
+
+make_partial!(PartialDcBlocker {} => DcBlocker);
+
+pub fn dc_blocker() -> PartialWaveBuilder<PartialDcBlocker> {
+    PartialDcBlocker::new()
+}
+
+/// How many times faster than the sample rate an oversampled shaper runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Oversampling {
+    X2,
+    X4,
+    X8,
+}
+
+impl Oversampling {
+    fn factor(self) -> usize {
+        match self {
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+            Oversampling::X8 => 8,
+        }
+    }
+}
+
+/// Butterworth lowpass at the edge of the regular audio band, for a shaper running `factor` times
+/// faster. The cookbook formulas assume 44.1kHz, so the cutoff is scaled down by the factor.
+/// There is one filter for the left and one for the right channel.
+fn resampling_filter(factor: usize) -> [Vec<Biquad>; 2] {
+    let cutoff = OVERSAMPLING_CUTOFF * 44100.0 / factor as f64;
+    let filter: Vec<_> = (0..OVERSAMPLING_ORDER / 2)
+        .map(|i| Biquad::lowpass(cutoff, Biquad::butterworth_q(OVERSAMPLING_ORDER, i)))
+        .collect();
+    [filter.clone(), filter]
+}
+
+#[inline]
+fn resample(filter: &mut [Biquad], x: f64) -> f64 {
+    filter
+        .iter_mut()
+        .fold(x, |acc, section| section.process(acc))
+}
+
+/// Applies the waveshaping function `shape` at a multiple of the sample rate and filters the
+/// result back down, so the harmonics it creates above the audible range don't fold back as
+/// aliasing.
+///
+/// Only memoryless shapers can be oversampled this way: `shape` sees one sample at a time and
+/// knows nothing of the rate it runs at. Oscillators, filters and envelopes all assume 44.1kHz.
+#[derive(Clone)]
+pub struct OversampledShaper<T, F> {
+    factor: usize,
+    /// Interpolation filters in front of the shaper and decimation filters behind it.
+    upsampler: [Vec<Biquad>; 2],
+    downsampler: [Vec<Biquad>; 2],
+    shape: F,
+    input: T,
+}
+
+impl<T, F> OversampledShaper<T, F> {
+    pub fn new(oversampling: Oversampling, shape: F, input: T) -> WaveGenerator<Self> {
+        let factor = oversampling.factor();
+        Self {
+            factor,
+            upsampler: resampling_filter(factor),
+            downsampler: resampling_filter(factor),
+            shape,
+            input,
+        }
+        .into()
+    }
+}
+
+impl<T, F: Fn(f64) -> f64> OversampledShaper<T, F> {
+    #[inline]
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        let mut out = 0.0;
+        for i in 0..self.factor {
+            // Zero stuffing spreads the energy of one sample over `factor` samples.
+            let x = if i == 0 { x * self.factor as f64 } else { 0.0 };
+            let y = (self.shape)(resample(&mut self.upsampler[channel], x));
+            out = resample(&mut self.downsampler[channel], y);
+        }
+        out
+    }
+}
+
+impl<W: Wave, F: Fn(f64) -> f64> Wave for OversampledShaper<W, F> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let x = self.input.next_sample();
+        self.process(0, x)
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        (self.process(0, l), self.process(1, r))
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialOversampledShaper<F> {
+    oversampling: Oversampling,
+    shape: F,
+}
+
+impl<F> PartialWave for PartialOversampledShaper<F>
+where
+    F: Fn(f64) -> f64 + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = OversampledShaper<W, F>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        OversampledShaper::new(self.oversampling, self.shape, input)
+    }
+}
+
+/// Shape every sample with `shape` at a multiple of the sample rate, e.g.
+/// `wave >> oversampled_shaper(Oversampling::X4, |x| (x * 8.0).tanh())`.
+pub fn oversampled_shaper<F>(
+    oversampling: Oversampling,
+    shape: F,
+) -> PartialWaveBuilder<PartialOversampledShaper<F>>
+where
+    F: Fn(f64) -> f64 + Clone + Send + Sync,
+{
+    PartialWaveBuilder::new(PartialOversampledShaper {
+        oversampling,
+        shape,
+    })
+}
+
+/// Soft clipping waveshaper. `drive` amplifies the input before it is squashed into -1.0..1.0.
+#[derive(Clone)]
+pub struct Distortion<T> {
+    drive: f64,
+    input: T,
+}
+
+impl<T> Distortion<T> {
+    pub fn new(drive: f64, input: T) -> WaveGenerator<Self> {
+        Self { drive, input }.into()
+    }
+}
+
+impl<W: Wave> Wave for Distortion<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        (self.input.next_sample() * self.drive).tanh()
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        ((l * self.drive).tanh(), (r * self.drive).tanh())
+    }
+}
+
+make_partial!(PartialDistortion { drive: f64 } => Distortion);
+
+pub fn distortion(drive: f64) -> PartialWaveBuilder<PartialDistortion> {
+    PartialDistortion::new(drive)
+}
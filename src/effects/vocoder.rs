@@ -0,0 +1,173 @@
+use crate::{
+    effects::{dynamics::PeakDetector, Biquad},
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    wave::{Wave, WaveGenerator},
+};
+
+/// Bandpass of 4th order, two identical biquads in a row, so neighbouring bands don't bleed into
+/// each other too much.
+#[derive(Clone)]
+struct BandFilter {
+    sections: [Biquad; 2],
+}
+
+impl BandFilter {
+    fn new(frequency: f64, q: f64) -> Self {
+        Self {
+            sections: [
+                Biquad::bandpass(frequency, q),
+                Biquad::bandpass(frequency, q),
+            ],
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f64) -> f64 {
+        let x = self.sections[0].process(x);
+        self.sections[1].process(x)
+    }
+}
+
+#[derive(Clone)]
+struct VocoderBand {
+    /// Filters for the left and right channel of the carrier.
+    carrier: [BandFilter; 2],
+    modulator: BandFilter,
+    envelope: PeakDetector,
+}
+
+/// Channel vocoder. Both the carrier (the input) and the modulator are split into `bands`
+/// bands, spaced logarithmically between `low` and `high` Hz. The envelope of every modulator band
+/// then controls the volume of the matching carrier band, so the carrier "speaks" like the
+/// modulator. `attack` and `release` (in seconds) set how fast the envelopes follow. A stereo
+/// carrier keeps its channels apart, the modulator is mono.
+#[derive(Clone)]
+pub struct Vocoder<T, M> {
+    bands: Vec<VocoderBand>,
+    modulator: M,
+    input: T,
+}
+
+impl<T, M> Vocoder<T, M> {
+    pub fn new(
+        bands: usize,
+        low: f64,
+        high: f64,
+        attack: f64,
+        release: f64,
+        modulator: M,
+        input: T,
+    ) -> WaveGenerator<Self> {
+        let bands = bands.max(2);
+        let spacing = (high / low).powf(1.0 / (bands - 1) as f64);
+        // Bandwidth of one band, so neighbouring bands meet at their -3 dB points.
+        let q = spacing.sqrt() / (spacing - 1.0);
+
+        Self {
+            bands: (0..bands)
+                .map(|i| {
+                    let frequency = low * spacing.powi(i as i32);
+                    VocoderBand {
+                        carrier: [BandFilter::new(frequency, q), BandFilter::new(frequency, q)],
+                        modulator: BandFilter::new(frequency, q),
+                        envelope: PeakDetector::new(attack, release),
+                    }
+                })
+                .collect(),
+            modulator,
+            input,
+        }
+        .into()
+    }
+}
+
+impl<W: Wave, M: Wave> Wave for Vocoder<W, M> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let carrier = self.input.next_sample();
+        let modulator = self.modulator.next_sample();
+
+        let sum = self.bands.iter_mut().fold(0.0, |acc, band| {
+            let envelope = band.envelope.process(band.modulator.process(modulator));
+            acc + band.carrier[0].process(carrier) * envelope
+        });
+
+        // Every band only carries a small part of the energy of both signals.
+        sum * self.bands.len() as f64
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.input.next_frame();
+        let modulator = self.modulator.next_sample();
+
+        let (l, r) = self
+            .bands
+            .iter_mut()
+            .fold((0.0, 0.0), |(acc_l, acc_r), band| {
+                let envelope = band.envelope.process(band.modulator.process(modulator));
+                (
+                    acc_l + band.carrier[0].process(l) * envelope,
+                    acc_r + band.carrier[1].process(r) * envelope,
+                )
+            });
+
+        let gain = self.bands.len() as f64;
+        (l * gain, r * gain)
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialVocoder<M> {
+    bands: usize,
+    low: f64,
+    high: f64,
+    attack: f64,
+    release: f64,
+    modulator: M,
+}
+
+impl<M> PartialWave for PartialVocoder<M>
+where
+    M: Wave + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = Vocoder<W, M>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        Vocoder::new(
+            self.bands,
+            self.low,
+            self.high,
+            self.attack,
+            self.release,
+            self.modulator,
+            input,
+        )
+    }
+}
+
+/// Vocoder that is fed the carrier through `>>`, e.g. `synth >> vocoder(voice, 16, 100.0,
+/// 8000.0, 0.005, 0.02)`.
+pub fn vocoder<M>(
+    modulator: WaveGenerator<M>,
+    bands: usize,
+    low: f64,
+    high: f64,
+    attack: f64,
+    release: f64,
+) -> PartialWaveBuilder<PartialVocoder<M>>
+where
+    M: Wave + Clone + Send + Sync,
+{
+    PartialWaveBuilder::new(PartialVocoder {
+        bands,
+        low,
+        high,
+        attack,
+        release,
+        modulator: modulator.source,
+    })
+}
@@ -0,0 +1,403 @@
+use std::f64::consts::TAU;
+
+use crate::{
+    instrument::midi_note_number_to_frequency,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    random::Random,
+    sampler::SampleBuffer,
+    wave::{Wave, WaveGenerator},
+    waves::Constant,
+};
+
+/// Upper limit of grains sounding at the same time, to keep dense clouds affordable.
+const MAX_GRAINS: usize = 128;
+
+/// Material grains are cut from.
+pub trait GrainSource {
+    /// Frame a grain at `position` (0.0 - 1.0) starts at, if it reads `span` frames.
+    fn start(&self, position: f64, span: f64) -> f64;
+
+    /// The frame at `position`, interpolated between neighbouring frames.
+    fn read(&self, position: f64) -> f64;
+
+    /// MIDI note the material sounds at when played at its original speed.
+    fn root_key(&self) -> f64;
+
+    /// Sample rate of the material relative to the rate of the graph.
+    fn rate(&self) -> f64;
+
+    /// Called once per sample, before any grain reads from the source.
+    fn record(&mut self) {}
+}
+
+impl GrainSource for SampleBuffer {
+    fn start(&self, position: f64, _span: f64) -> f64 {
+        position.clamp(0.0, 1.0) * self.frames() as f64
+    }
+
+    fn read(&self, position: f64) -> f64 {
+        if position < 0.0 {
+            return 0.0;
+        }
+        let index = position as usize;
+        let fraction = position - index as f64;
+        // Grains are mono, so all channels are mixed down.
+        let frame = |index| {
+            (0..self.channels)
+                .map(|channel| self.frame(index, channel))
+                .sum::<f64>()
+                / self.channels as f64
+        };
+        let (a, b) = (frame(index), frame(index + 1));
+        a + (b - a) * fraction
+    }
+
+    fn root_key(&self) -> f64 {
+        self.root_key
+    }
+
+    fn rate(&self) -> f64 {
+        self.sample_rate as f64 / 44100.0
+    }
+}
+
+/// Records a wave into a ring buffer of the last `length` seconds, so it can be granulated live.
+/// Position 0.0 is the oldest part of the recording, 1.0 the newest a grain can start at without
+/// catching up with the recording.
+#[derive(Clone)]
+pub struct LiveInput<W> {
+    buffer: Vec<f64>,
+    /// Number of samples recorded so far.
+    written: usize,
+    root_key: f64,
+    input: W,
+}
+
+impl<W> LiveInput<W> {
+    pub fn new(length: f64, input: WaveGenerator<W>) -> Self {
+        Self {
+            buffer: vec![0.0; ((length * 44100.0) as usize).max(1)],
+            written: 0,
+            root_key: 60.0,
+            input: input.source,
+        }
+    }
+
+    /// The note the recorded material is assumed to sound at. Defaults to middle C.
+    pub fn with_root_key(mut self, root_key: f64) -> Self {
+        self.root_key = root_key;
+        self
+    }
+}
+
+impl<W: Wave> GrainSource for LiveInput<W> {
+    fn start(&self, position: f64, span: f64) -> f64 {
+        let oldest = self.written.saturating_sub(self.buffer.len()) as f64;
+        let newest = (self.written as f64 - span).max(oldest);
+        oldest + (newest - oldest) * position.clamp(0.0, 1.0)
+    }
+
+    fn read(&self, position: f64) -> f64 {
+        // Only the frames still in the ring buffer can be read.
+        let oldest = self.written.saturating_sub(self.buffer.len()) as f64;
+        if position < oldest || position >= self.written as f64 {
+            return 0.0;
+        }
+        let index = position as usize;
+        let fraction = position - index as f64;
+        let a = self.buffer[index % self.buffer.len()];
+        let b = if index + 1 < self.written {
+            self.buffer[(index + 1) % self.buffer.len()]
+        } else {
+            a
+        };
+        a + (b - a) * fraction
+    }
+
+    fn root_key(&self) -> f64 {
+        self.root_key
+    }
+
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    fn record(&mut self) {
+        let len = self.buffer.len();
+        self.buffer[self.written % len] = self.input.next_sample();
+        self.written += 1;
+    }
+}
+
+/// Settings for the next grain, read from the controls of a `Granular`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrainSettings {
+    /// Grains started per second.
+    pub density: f64,
+    /// Length of a grain in seconds.
+    pub size: f64,
+    /// Where grains start in the source, between 0.0 and 1.0.
+    pub position: f64,
+    /// Random offset added to the position, up to this fraction of the source in either direction.
+    pub spray: f64,
+    /// Random detune of each grain, up to this many cents in either direction.
+    pub detune: f64,
+}
+
+/// Waves that control a `Granular`. Every parameter is a constant by default and can be replaced
+/// by any wave, e.g. an LFO that sweeps the position through the source.
+///
+/// ```
+/// use rust_audio_shenanigans::granular::GrainControls;
+/// use rust_audio_shenanigans::waves::{constant, sine};
+///
+/// let controls = GrainControls::new()
+///     .with_density(constant(40.0))
+///     .with_position((constant(0.1) >> sine()) * 0.5 + 0.5)
+///     .with_detune(constant(15.0));
+/// ```
+#[derive(Clone)]
+pub struct GrainControls<D = Constant, L = Constant, P = Constant, S = Constant, J = Constant> {
+    density: D,
+    size: L,
+    position: P,
+    spray: S,
+    detune: J,
+}
+
+impl GrainControls {
+    /// 20 grains per second, 100ms each, from the start of the source and without randomization.
+    pub fn new() -> Self {
+        Self {
+            density: Constant { value: 20.0 },
+            size: Constant { value: 0.1 },
+            position: Constant { value: 0.0 },
+            spray: Constant { value: 0.0 },
+            detune: Constant { value: 0.0 },
+        }
+    }
+}
+
+impl Default for GrainControls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, L, P, S, J> GrainControls<D, L, P, S, J> {
+    pub fn with_density<W>(self, density: WaveGenerator<W>) -> GrainControls<W, L, P, S, J> {
+        GrainControls {
+            density: density.source,
+            size: self.size,
+            position: self.position,
+            spray: self.spray,
+            detune: self.detune,
+        }
+    }
+
+    pub fn with_size<W>(self, size: WaveGenerator<W>) -> GrainControls<D, W, P, S, J> {
+        GrainControls {
+            density: self.density,
+            size: size.source,
+            position: self.position,
+            spray: self.spray,
+            detune: self.detune,
+        }
+    }
+
+    pub fn with_position<W>(self, position: WaveGenerator<W>) -> GrainControls<D, L, W, S, J> {
+        GrainControls {
+            density: self.density,
+            size: self.size,
+            position: position.source,
+            spray: self.spray,
+            detune: self.detune,
+        }
+    }
+
+    pub fn with_spray<W>(self, spray: WaveGenerator<W>) -> GrainControls<D, L, P, W, J> {
+        GrainControls {
+            density: self.density,
+            size: self.size,
+            position: self.position,
+            spray: spray.source,
+            detune: self.detune,
+        }
+    }
+
+    pub fn with_detune<W>(self, detune: WaveGenerator<W>) -> GrainControls<D, L, P, S, W> {
+        GrainControls {
+            density: self.density,
+            size: self.size,
+            position: self.position,
+            spray: self.spray,
+            detune: detune.source,
+        }
+    }
+}
+
+impl<D: Wave, L: Wave, P: Wave, S: Wave, J: Wave> GrainControls<D, L, P, S, J> {
+    fn next(&mut self) -> GrainSettings {
+        GrainSettings {
+            density: self.density.next_sample(),
+            size: self.size.next_sample(),
+            position: self.position.next_sample(),
+            spray: self.spray.next_sample(),
+            detune: self.detune.next_sample(),
+        }
+    }
+}
+
+/// A single grain: a stretch of the source faded in and out by a Hann window.
+#[derive(Clone)]
+struct Grain {
+    position: f64,
+    step: f64,
+    age: usize,
+    length: usize,
+    gain: f64,
+}
+
+impl Grain {
+    fn next_sample(&mut self, source: &impl GrainSource) -> f64 {
+        let window = 0.5 - 0.5 * (TAU * self.age as f64 / self.length as f64).cos();
+        let sample = source.read(self.position) * window * self.gain;
+        self.position += self.step;
+        self.age += 1;
+        sample
+    }
+
+    fn finished(&self) -> bool {
+        self.age >= self.length
+    }
+}
+
+/// Granular synthesizer. Emits overlapping, windowed grains cut from a [`GrainSource`], either a
+/// [`SampleBuffer`] or a [`LiveInput`].
+///
+/// The input is the frequency to play the grains at, like for a `SamplePlayer`: grains play at the
+/// original speed of the source when it matches the frequency of the root key. That way a
+/// granular synthesizer works as a patch of a `PolyInstrument` as well.
+#[derive(Clone)]
+pub struct Granular<S, C, T> {
+    source: S,
+    controls: C,
+    grains: Vec<Grain>,
+    /// Samples until the next grain starts.
+    countdown: f64,
+    random: Random,
+    input: T,
+}
+
+impl<S, C, T> Granular<S, C, T> {
+    pub fn new(source: S, controls: C, input: T) -> WaveGenerator<Self> {
+        Self {
+            source,
+            controls,
+            grains: Vec::new(),
+            countdown: 0.0,
+            random: Random::new(),
+            input,
+        }
+        .into()
+    }
+}
+
+impl<S: GrainSource, D, L, P, R, J, T> Granular<S, GrainControls<D, L, P, R, J>, T>
+where
+    D: Wave,
+    L: Wave,
+    P: Wave,
+    R: Wave,
+    J: Wave,
+    T: Wave,
+{
+    fn spawn(&mut self, settings: &GrainSettings, frequency: f64) {
+        let length = ((settings.size * 44100.0) as usize).max(1);
+        let detune = self.random.bipolar() * settings.detune;
+        let step = frequency / midi_note_number_to_frequency(self.source.root_key())
+            * 2.0f64.powf(detune / 1200.0)
+            * self.source.rate();
+
+        let position = settings.position + self.random.bipolar() * settings.spray;
+        let start = self.source.start(position, length as f64 * step);
+
+        // Overlapping Hann windows add up to half the number of overlapping grains, which would
+        // make denser clouds louder.
+        let overlap = settings.density * settings.size / 2.0;
+
+        if self.grains.len() < MAX_GRAINS {
+            self.grains.push(Grain {
+                position: start,
+                step,
+                age: 0,
+                length,
+                gain: 1.0 / overlap.max(1.0),
+            });
+        }
+    }
+}
+
+impl<S: GrainSource, D, L, P, R, J, T> Wave for Granular<S, GrainControls<D, L, P, R, J>, T>
+where
+    D: Wave,
+    L: Wave,
+    P: Wave,
+    R: Wave,
+    J: Wave,
+    T: Wave,
+{
+    fn next_sample(&mut self) -> f64 {
+        let frequency = self.input.next_sample();
+        let settings = self.controls.next();
+        self.source.record();
+
+        // The countdown waits while the density is zero, so no backlog of grains builds up.
+        if settings.density > 0.0 {
+            self.countdown -= 1.0;
+            if self.countdown <= 0.0 {
+                self.spawn(&settings, frequency);
+                self.countdown += 44100.0 / settings.density;
+            }
+        }
+
+        let source = &self.source;
+        let out = self
+            .grains
+            .iter_mut()
+            .map(|grain| grain.next_sample(source))
+            .sum();
+        self.grains.retain(|grain| !grain.finished());
+        out
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialGranular<S, C> {
+    source: S,
+    controls: C,
+}
+
+impl<S, D, L, P, R, J> PartialWave for PartialGranular<S, GrainControls<D, L, P, R, J>>
+where
+    S: GrainSource + Clone + Send + Sync,
+    D: Wave + Clone + Send + Sync,
+    L: Wave + Clone + Send + Sync,
+    P: Wave + Clone + Send + Sync,
+    R: Wave + Clone + Send + Sync,
+    J: Wave + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = Granular<S, GrainControls<D, L, P, R, J>, W>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        Granular::new(self.source, self.controls, input)
+    }
+}
+
+/// Granulate `source`, e.g. `constant(261.63) >> granular(buffer, GrainControls::new())`.
+pub fn granular<S, C>(source: S, controls: C) -> PartialWaveBuilder<PartialGranular<S, C>> {
+    PartialWaveBuilder::new(PartialGranular { source, controls })
+}
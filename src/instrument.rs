@@ -1,71 +1,643 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 
 use crate::{
     partial_wave::PartialWave,
+    tuning::Tuning,
     wave::{Wave, WaveGenerator},
-    waves::{constant, ADSREvent, ADSRTrigger, Constant, ADSR},
+    waves::{ADSREvent, ADSRTrigger, MixMul, WaveMixer, ADSR},
 };
 
-fn midi_note_number_to_frequency<T: Into<f64>>(note: T) -> f64 {
+mod controls;
+mod glide;
+mod unison;
+
+pub use controls::{
+    BentFrequency, Control, ControlWave, Controls, CONTROLLER_EXPRESSION, CONTROLLER_MOD_WHEEL,
+    CONTROLLER_TIMBRE, DEFAULT_PITCH_BEND_RANGE,
+};
+pub use glide::{Glide, GlideCurve, Portamento};
+pub use unison::{unison, PhaseOffset, Unison, UnisonWave};
+
+/// The frequency of a key in twelve-tone equal temperament at A4 = 440 Hz. Instruments map keys to
+/// frequencies with their [`Tuning`].
+pub fn midi_note_number_to_frequency<T: Into<f64>>(note: T) -> f64 {
     2.0f64.powf((note.into() - 69.0) / 12.0) * 440.0
 }
 
-type InstrumentWave<W: Wave> = impl Wave;
-type Keymap<T> = Arc<Mutex<HashMap<usize, (InstrumentWave<T>, ADSRTrigger)>>>;
+/// The note a voice is started for.
+#[derive(Clone)]
+pub struct Note {
+    pub key: usize,
+    /// Velocity between 1 and 127.
+    pub velocity: u8,
+    /// Frequency of the key. Notes that glide start elsewhere and end up here.
+    pub frequency: f64,
+    /// The pitch of the note, which moves when a monophonic instrument plays legato or glides.
+    pub glide: Glide,
+    /// Pitch bend, aftertouch and controllers of the instrument that plays the note.
+    pub controls: Controls,
+    /// Pitch bend, pressure and timbre of this note alone, e.g. from an MPE member channel. Notes
+    /// played without expression get controls of their own that stay at rest.
+    pub expression: Controls,
+}
+
+impl Note {
+    /// The frequency of the note as a wave that follows its glide and the pitch bend of the
+    /// instrument and of the note.
+    pub fn bent_frequency(&self) -> WaveGenerator<BentFrequency> {
+        BentFrequency::new(&self.glide, &self.controls, &self.expression).into()
+    }
+
+    /// The pressure on this note, between 0.0 and 1.0.
+    pub fn pressure(&self) -> WaveGenerator<ControlWave> {
+        self.expression.wave(Control::ChannelPressure)
+    }
+
+    /// The timbre (CC74) of this note, between 0.0 and 1.0.
+    pub fn timbre(&self) -> WaveGenerator<ControlWave> {
+        self.expression.wave(Control::Controller(CONTROLLER_TIMBRE))
+    }
+}
+
+/// Envelope triggers of a single voice. Voices made of several layers have one trigger per layer.
+pub struct VoiceTrigger {
+    triggers: Vec<ADSRTrigger>,
+}
+
+impl VoiceTrigger {
+    pub fn new(triggers: Vec<ADSRTrigger>) -> Self {
+        Self { triggers }
+    }
+
+    pub fn trigger(&self, e: ADSREvent) {
+        for trigger in &self.triggers {
+            trigger.trigger(e);
+        }
+    }
+
+    /// Whether all envelopes have faded out.
+    pub fn is_idle(&self) -> bool {
+        self.triggers.iter().all(|trigger| trigger.is_idle())
+    }
+}
+
+impl From<ADSRTrigger> for VoiceTrigger {
+    fn from(trigger: ADSRTrigger) -> Self {
+        Self::new(vec![trigger])
+    }
+}
+
+/// The envelope partial waves are shaped by when they are played as a patch.
+fn default_envelope() -> (WaveGenerator<ADSR>, ADSRTrigger) {
+    ADSR::new(0.02, 0.3, 0.5, 0.05)
+}
 
-pub struct PolyInstrument<T>
+/// A sound a `PolyInstrument` can play. For every note, the patch builds a voice together with the
+/// triggers of its envelopes.
+///
+/// Every partial wave is a patch: it is fed the frequency of the note, bent by the pitch bend, and
+/// shaped by a default envelope. Patches like the `Sampler` look at the key and the velocity as
+/// well and bring their own envelopes.
+pub trait Patch {
+    type Voice: Wave + Send;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger);
+}
+
+impl<T> Patch for T
 where
-    T: PartialWave,
+    T: PartialWave + Clone,
 {
-    source: T,
-    keymap: Keymap<T::Target<Constant>>,
+    type Voice = WaveMixer<MixMul, T::Target<BentFrequency>, ADSR>;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        let (adsr, trigger) = default_envelope();
+        let wave = note.bent_frequency() >> self.clone();
+        ((wave * adsr).source, trigger.into())
+    }
 }
 
+/// Builds voices with a function, for patches that need more of the note than its frequency, e.g.
+/// to follow the mod wheel.
+///
+/// ```
+/// use rust_audio_shenanigans::instrument::{patch_fn, PolyInstrument};
+/// use rust_audio_shenanigans::waves::{constant, sine, triangle, ADSR};
+///
+/// let vibrato = patch_fn(|note| {
+///     let depth = note.controls.mod_wheel() * 8.0;
+///     let frequency = note.bent_frequency() + (constant(5.5) >> sine()) * depth;
+///     let (adsr, trigger) = ADSR::new(0.02, 0.3, 0.5, 0.05);
+///     (((frequency >> triangle()) * adsr).source, trigger.into())
+/// });
+/// let (mut inst, wave) = PolyInstrument::new(vibrato);
+/// ```
 #[derive(Clone)]
-pub struct PolyInstrumentWave<T>
+pub struct PatchFn<F>(F);
+
+pub fn patch_fn<F, V>(f: F) -> PatchFn<F>
 where
-    T: PartialWave,
+    F: Fn(&Note) -> (V, VoiceTrigger),
+    V: Wave + Send,
 {
-    keymap: Keymap<T::Target<Constant>>,
+    PatchFn(f)
 }
 
-impl<W, T> PolyInstrument<T>
+impl<F, V> Patch for PatchFn<F>
 where
-    W: Wave,
-    T: PartialWave<Target<Constant> = W> + Clone,
+    F: Fn(&Note) -> (V, VoiceTrigger),
+    V: Wave + Send,
 {
-    fn make_instrument(&self, note: usize) -> (InstrumentWave<W>, ADSRTrigger) {
-        let (adsr, trigger) = ADSR::new(0.02, 0.3, 0.5, 0.05);
-        let freq = constant(midi_note_number_to_frequency(note as u8));
-        let wave = freq >> self.source.clone();
-        let wave = wave * adsr;
-        (wave, trigger)
+    type Voice = V;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        (self.0)(note)
+    }
+}
+
+type DynVoice = Box<dyn Wave + Send>;
+
+/// Boxes the voices of a patch, so patches of different types can be used interchangeably.
+struct BoxedVoices<P>(P);
+
+impl<P> Patch for BoxedVoices<P>
+where
+    P: Patch,
+    P::Voice: 'static,
+{
+    type Voice = DynVoice;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        let (voice, trigger) = self.0.voice(note);
+        (Box::new(voice), trigger)
+    }
+}
+
+/// A patch of any type, e.g. for instruments that switch between a `Sampler` and a `DrumKit`.
+/// Cloning only clones a reference to the patch.
+#[derive(Clone)]
+pub struct SharedPatch {
+    patch: Arc<dyn Patch<Voice = DynVoice> + Send + Sync>,
+}
+
+impl SharedPatch {
+    pub fn new<P>(patch: P) -> Self
+    where
+        P: Patch + Send + Sync + 'static,
+        P::Voice: 'static,
+    {
+        Self {
+            patch: Arc::new(BoxedVoices(patch)),
+        }
+    }
+}
+
+impl Patch for SharedPatch {
+    type Voice = DynVoice;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        self.patch.voice(note)
+    }
+}
+
+type Voice<V> = (V, VoiceTrigger);
+type Keymap<V> = Arc<Mutex<Voices<V>>>;
+
+/// What holds a voice: its key and, for notes from one of several sources sharing the instrument,
+/// the source. Notes from different sources can sound on the same key at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Slot {
+    source: Option<u8>,
+    key: usize,
+}
+
+impl From<usize> for Slot {
+    fn from(key: usize) -> Self {
+        Self { source: None, key }
+    }
+}
+
+/// Velocity notes are scaled by while the soft pedal is down.
+const SOFT_PEDAL_VELOCITY: f64 = 0.7;
+
+/// Voices of a `PolyInstrument`. Voices are kept by slot until they are released, either when the
+/// key goes up or, with a pedal down, when the pedal goes up. Released voices keep playing until
+/// their envelope has faded out and are dropped afterwards.
+struct Voices<V> {
+    held: HashMap<Slot, Voice<V>>,
+    /// Keys that are down, as opposed to voices only kept by a pedal.
+    pressed: HashSet<Slot>,
+    sustain: bool,
+    /// Keys caught by the sostenuto pedal, while it is down.
+    sostenuto: Option<HashSet<Slot>>,
+    releasing: Vec<Voice<V>>,
+}
+
+impl<V: Wave> Voices<V> {
+    fn new() -> Self {
+        Self {
+            held: HashMap::new(),
+            pressed: HashSet::new(),
+            sustain: false,
+            sostenuto: None,
+            releasing: Vec::new(),
+        }
+    }
+
+    fn press(&mut self, key: Slot, voice: Voice<V>) {
+        // A key struck again while a pedal keeps it sounding starts over as well.
+        self.release(key);
+        self.held.insert(key, voice);
+        self.pressed.insert(key);
+    }
+
+    /// Move the voice of `from` to `to`, e.g. when a monophonic instrument plays legato.
+    fn rekey(&mut self, from: Slot, to: Slot) {
+        if let Some(voice) = self.held.remove(&from) {
+            self.release(to);
+            self.held.insert(to, voice);
+        }
+    }
+
+    fn key_up(&mut self, key: Slot) {
+        self.pressed.remove(&key);
+        self.release_unheld();
+    }
+
+    fn set_sustain(&mut self, down: bool) {
+        self.sustain = down;
+        self.release_unheld();
+    }
+
+    fn set_sostenuto(&mut self, down: bool) {
+        match down {
+            true if self.sostenuto.is_none() => self.sostenuto = Some(self.pressed.clone()),
+            true => {}
+            false => {
+                self.sostenuto = None;
+                self.release_unheld();
+            }
+        }
+    }
+
+    /// Release the voices neither a key nor a pedal is holding.
+    fn release_unheld(&mut self) {
+        if self.sustain {
+            return;
+        }
+        let unheld: Vec<Slot> = self
+            .held
+            .keys()
+            .filter(|key| {
+                let caught = self
+                    .sostenuto
+                    .as_ref()
+                    .is_some_and(|keys| keys.contains(key));
+                !self.pressed.contains(key) && !caught
+            })
+            .copied()
+            .collect();
+        for key in unheld {
+            self.release(key);
+        }
+    }
+
+    fn release(&mut self, key: Slot) {
+        if let Some(voice) = self.held.remove(&key) {
+            voice.1.trigger(ADSREvent::Release);
+            self.releasing.push(voice);
+        }
     }
 
+    /// Release all voices, no matter what holds them.
+    fn release_all(&mut self) {
+        let keys: Vec<Slot> = self.held.keys().copied().collect();
+        for key in keys {
+            self.release(key);
+        }
+    }
+
+    /// Let go of all keys and pedals, releasing all voices.
+    fn reset(&mut self) {
+        self.pressed.clear();
+        self.sustain = false;
+        self.sostenuto = None;
+        self.release_all();
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.releasing.retain(|(_, trigger)| !trigger.is_idle());
+
+        self.held
+            .values_mut()
+            .chain(self.releasing.iter_mut())
+            .fold((0.0, 0.0), |(l, r), (wave, _)| {
+                let (wl, wr) = wave.next_frame();
+                (l + wl, r + wr)
+            })
+    }
+
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+}
+
+/// Which of the held keys a monophonic instrument plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotePriority {
+    /// The key pressed most recently.
+    Last,
+    /// The lowest key.
+    Low,
+    /// The highest key.
+    High,
+}
+
+impl NotePriority {
+    fn pick<'a>(&self, keys: &'a [HeldKey]) -> Option<&'a HeldKey> {
+        match self {
+            NotePriority::Last => keys.last(),
+            NotePriority::Low => keys.iter().min_by_key(|held| held.key),
+            NotePriority::High => keys.iter().max_by_key(|held| held.key),
+        }
+    }
+}
+
+/// How many notes an instrument plays at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// A voice for every key.
+    Poly,
+    /// A single voice, playing the held key `priority` picks. Releasing that key goes back to the
+    /// key picked next. With `legato`, the sounding voice moves to the next note and its envelope
+    /// carries on, otherwise every note starts a fresh voice.
+    Mono {
+        priority: NotePriority,
+        legato: bool,
+    },
+}
+
+/// A key held down on a monophonic instrument, remembered to go back to.
+#[derive(Clone)]
+struct HeldKey {
+    key: usize,
+    velocity: u8,
+    frequency: f64,
+    expression: Controls,
+}
+
+pub struct PolyInstrument<P>
+where
+    P: Patch,
+{
+    patch: P,
+    controls: Controls,
+    soft: bool,
+    mode: Mode,
+    portamento: Option<Portamento>,
+    tuning: Tuning,
+    /// Keys held down in mono mode, in the order they were pressed.
+    keys: Vec<HeldKey>,
+    /// The key and the pitch of the voice of mono mode.
+    sounding: Option<(usize, Glide)>,
+    keymap: Keymap<P::Voice>,
+}
+
+pub struct PolyInstrumentWave<P>
+where
+    P: Patch,
+{
+    keymap: Keymap<P::Voice>,
+}
+
+impl<P: Patch> Clone for PolyInstrumentWave<P> {
+    fn clone(&self) -> Self {
+        Self {
+            keymap: self.keymap.clone(),
+        }
+    }
+}
+
+impl<P> PolyInstrument<P>
+where
+    P: Patch,
+{
+    /// Start or stop the note `key`. Pressing a key that is already sounding starts a fresh voice,
+    /// so sources like samples start over, while the old voice fades out. Releasing a key while
+    /// the sustain pedal holds it, or the sostenuto pedal caught it, lets it sound on until the
+    /// pedal goes up.
     pub fn play(&mut self, key: usize, e: ADSREvent) {
-        let mut keymap = self.keymap.lock().unwrap();
-        if let Some((_, trigger)) = keymap.get(&key) {
-            trigger.trigger(e);
-        } else {
-            // let (wave, trigger) = self.make_instrument(key);
-            let inst = self.make_instrument(key);
-            inst.1.trigger(e);
-            keymap.insert(key, inst);
+        match e {
+            ADSREvent::Press(velocity) => self.press_expressive(key, velocity, Controls::new()),
+            ADSREvent::Release => self.release(key.into()),
+        }
+    }
+
+    /// Start the note `key`, modulated by its own `expression` on top of the controls of the
+    /// instrument. The expression is shared with the voice, so it can change while the note plays.
+    ///
+    /// ```
+    /// use rust_audio_shenanigans::instrument::{Controls, PolyInstrument};
+    /// use rust_audio_shenanigans::waves::{sine, ADSREvent};
+    ///
+    /// let (mut inst, wave) = PolyInstrument::new(sine());
+    /// let expression = Controls::new();
+    /// expression.set_pitch_bend_range(48.0);
+    /// inst.press_expressive(60, 100, expression.clone());
+    /// // Slide this note alone up by a fifth.
+    /// expression.set_pitch_bend(7.0 / 48.0);
+    /// inst.play(60, ADSREvent::Release);
+    /// ```
+    pub fn press_expressive(&mut self, key: usize, velocity: u8, expression: Controls) {
+        self.press(key.into(), velocity, expression);
+    }
+
+    /// Start the note `key` for `source`, like [`press_expressive`](Self::press_expressive). Every
+    /// source has voices of its own, e.g. the member channels of an MPE zone can play the same key
+    /// at once. In mono mode the source is ignored.
+    pub fn press_from(&mut self, source: u8, key: usize, velocity: u8, expression: Controls) {
+        let source = Some(source);
+        self.press(Slot { source, key }, velocity, expression);
+    }
+
+    /// Stop the note `key` started by [`press_from`](Self::press_from).
+    pub fn release_from(&mut self, source: u8, key: usize) {
+        let source = Some(source);
+        self.release(Slot { source, key });
+    }
+
+    fn press(&mut self, slot: Slot, velocity: u8, expression: Controls) {
+        let key = slot.key;
+        let velocity = match self.soft {
+            true => ((velocity as f64 * SOFT_PEDAL_VELOCITY).round() as u8).max(1),
+            false => velocity,
+        };
+        // Keys the tuning leaves out stay silent.
+        let Some(frequency) = self.tuning.frequency(key) else {
+            return;
+        };
+        let held = HeldKey {
+            key,
+            velocity,
+            frequency,
+            expression,
+        };
+
+        match self.mode {
+            Mode::Poly => {
+                let glide = Glide::new(frequency);
+                let voice = self.voice(&held, glide);
+                self.keymap.lock().unwrap().press(slot, voice);
+            }
+            Mode::Mono { priority, legato } => {
+                self.keys.retain(|other| other.key != key);
+                self.keys.push(held.clone());
+                self.keymap.lock().unwrap().pressed.insert(key.into());
+                if priority.pick(&self.keys).map(|picked| picked.key) == Some(key) {
+                    self.play_mono(held, legato);
+                }
+            }
         }
-        if e == ADSREvent::Release {
-            // keymap.remove(&key);
-            // TODO: handle release event (remove from keymap after release time somehow)
+    }
+
+    fn release(&mut self, slot: Slot) {
+        let key = slot.key;
+        match self.mode {
+            Mode::Poly => self.keymap.lock().unwrap().key_up(slot),
+            Mode::Mono { priority, legato } => {
+                self.keys.retain(|held| held.key != key);
+                let sounding = self.sounding.as_ref().map(|(sounding, _)| *sounding);
+                if sounding == Some(key) {
+                    if let Some(next) = priority.pick(&self.keys).cloned() {
+                        self.play_mono(next, legato);
+                    }
+                }
+                self.keymap.lock().unwrap().key_up(key.into());
+            }
+        }
+    }
+
+    fn voice(&self, held: &HeldKey, glide: Glide) -> Voice<P::Voice> {
+        let note = Note {
+            key: held.key,
+            velocity: held.velocity,
+            frequency: held.frequency,
+            glide,
+            controls: self.controls.clone(),
+            expression: held.expression.clone(),
+        };
+        let voice = self.patch.voice(&note);
+        voice.1.trigger(ADSREvent::Press(held.velocity));
+        voice
+    }
+
+    /// Move the voice of mono mode to `next`, or start a new one.
+    fn play_mono(&mut self, next: HeldKey, legato: bool) {
+        let frequency = next.frequency;
+        let mut voices = self.keymap.lock().unwrap();
+
+        match self.sounding.take() {
+            Some((key, glide)) if legato && voices.held.contains_key(&key.into()) => {
+                glide.glide_to(frequency, self.portamento);
+                voices.rekey(key.into(), next.key.into());
+                self.sounding = Some((next.key, glide));
+            }
+            previous => {
+                let glide = match &previous {
+                    Some((_, from)) if self.portamento.is_some() => {
+                        let glide = Glide::new(from.target());
+                        glide.glide_to(frequency, self.portamento);
+                        glide
+                    }
+                    _ => Glide::new(frequency),
+                };
+                if let Some((key, _)) = previous {
+                    voices.release(key.into());
+                }
+                drop(voices);
+
+                let voice = self.voice(&next, glide.clone());
+                self.keymap.lock().unwrap().press(next.key.into(), voice);
+                self.sounding = Some((next.key, glide));
+            }
         }
     }
 
-    pub fn new(source: T) -> (Self, WaveGenerator<PolyInstrumentWave<T>>) {
-        let keymap = Arc::new(Mutex::new(HashMap::new()));
+    /// Switch between playing a voice for every key and a single voice. Sounding notes are
+    /// released.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.keys.clear();
+        self.sounding = None;
+        self.keymap.lock().unwrap().release_all();
+    }
+
+    /// Release every note, as if all keys and pedals went up at once, e.g. when playback jumps
+    /// to another part of a song.
+    pub fn release_all(&mut self) {
+        self.keys.clear();
+        self.sounding = None;
+        self.soft = false;
+        self.keymap.lock().unwrap().reset();
+    }
+
+    /// Glide from note to note in mono mode, or jump to the next note without `portamento`.
+    pub fn set_portamento(&mut self, portamento: Option<Portamento>) {
+        self.portamento = portamento;
+    }
+
+    /// Hold all notes that are released while the pedal is down, until it goes up (CC64).
+    pub fn set_sustain(&mut self, down: bool) {
+        self.keymap.lock().unwrap().set_sustain(down);
+    }
+
+    /// Hold the notes whose keys are down at the moment the pedal goes down, until it goes up
+    /// again (CC66). Notes pressed while the pedal is down are not affected.
+    pub fn set_sostenuto(&mut self, down: bool) {
+        self.keymap.lock().unwrap().set_sostenuto(down);
+    }
+
+    /// Play notes pressed while the pedal is down more softly (CC67). Patches that want to change
+    /// their timbre as well can follow the controller in the `Controls`.
+    pub fn set_soft_pedal(&mut self, down: bool) {
+        self.soft = down;
+    }
+
+    /// Pitch bend, aftertouch and controllers the voices of this instrument are modulated by.
+    pub fn controls(&self) -> &Controls {
+        &self.controls
+    }
+
+    /// Tune the keys pressed from now on with `tuning`.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Play notes pressed from now on with `patch`. Sounding notes keep their voices.
+    pub fn set_patch(&mut self, patch: P) {
+        self.patch = patch;
+    }
+
+    pub fn new(patch: P) -> (Self, WaveGenerator<PolyInstrumentWave<P>>) {
+        let keymap = Arc::new(Mutex::new(Voices::new()));
         (
             Self {
-                source,
+                patch,
+                controls: Controls::new(),
+                soft: false,
+                mode: Mode::Poly,
+                portamento: None,
+                tuning: Tuning::default(),
+                keys: Vec::new(),
+                sounding: None,
                 keymap: keymap.clone(),
             },
             PolyInstrumentWave { keymap }.into(),
@@ -73,28 +645,28 @@ where
     }
 }
 
-impl<T> Wave for PolyInstrument<T>
+impl<P> Wave for PolyInstrument<P>
 where
-    T: PartialWave + Clone,
+    P: Patch,
 {
     fn next_sample(&mut self) -> f64 {
-        self.keymap
-            .lock()
-            .unwrap()
-            .values_mut()
-            .fold(0.0, |acc, (wave, _)| acc + wave.next_sample())
+        self.keymap.lock().unwrap().next_sample()
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.keymap.lock().unwrap().next_frame()
     }
 }
 
-impl<T> Wave for PolyInstrumentWave<T>
+impl<P> Wave for PolyInstrumentWave<P>
 where
-    T: PartialWave + Clone,
+    P: Patch,
 {
     fn next_sample(&mut self) -> f64 {
-        self.keymap
-            .lock()
-            .unwrap()
-            .values_mut()
-            .fold(0.0, |acc, (wave, _)| acc + wave.next_sample())
+        self.keymap.lock().unwrap().next_sample()
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.keymap.lock().unwrap().next_frame()
     }
 }
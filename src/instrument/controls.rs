@@ -0,0 +1,212 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use super::glide::{Glide, Gliding};
+use crate::wave::{Wave, WaveGenerator};
+
+pub const CONTROLLER_MOD_WHEEL: u8 = 1;
+pub const CONTROLLER_EXPRESSION: u8 = 11;
+/// Brightness, the third dimension of MPE controllers besides pitch bend and pressure.
+pub const CONTROLLER_TIMBRE: u8 = 74;
+
+/// Pitch bend range General MIDI starts out with, in semitones.
+pub const DEFAULT_PITCH_BEND_RANGE: f64 = 2.0;
+
+#[derive(Default)]
+pub(super) struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub(super) fn new(value: f64) -> Self {
+        Self(AtomicU64::new(value.to_bits()))
+    }
+
+    pub(super) fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(super) fn store(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+struct ControlState {
+    pitch_bend: AtomicF64,
+    pitch_bend_range: AtomicF64,
+    channel_pressure: AtomicF64,
+    key_pressure: [AtomicF64; 128],
+    controllers: [AtomicF64; 128],
+}
+
+/// A modulation source of a `Controls`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Pitch bend in semitones, scaled by the pitch bend range.
+    PitchBend,
+    /// Channel aftertouch between 0.0 and 1.0.
+    ChannelPressure,
+    /// Polyphonic aftertouch of a key between 0.0 and 1.0.
+    KeyPressure(u8),
+    /// A MIDI controller between 0.0 and 1.0.
+    Controller(u8),
+}
+
+/// Pitch bend, aftertouch and controller values of one instrument, shared between the thread that
+/// receives MIDI messages and the voices that are modulated by them. Cloning shares the values.
+///
+/// Voices read the values through [`Controls::wave`], so they follow a controller while they
+/// play. The `Note` handed to a patch carries the controls of its instrument.
+#[derive(Clone)]
+pub struct Controls {
+    state: Arc<ControlState>,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(ControlState {
+                pitch_bend: AtomicF64::new(0.0),
+                pitch_bend_range: AtomicF64::new(DEFAULT_PITCH_BEND_RANGE),
+                channel_pressure: AtomicF64::new(0.0),
+                key_pressure: std::array::from_fn(|_| AtomicF64::default()),
+                controllers: std::array::from_fn(|_| AtomicF64::default()),
+            }),
+        }
+    }
+
+    /// Set the pitch bend between -1.0 and 1.0.
+    pub fn set_pitch_bend(&self, bend: f64) {
+        self.state.pitch_bend.store(bend.clamp(-1.0, 1.0));
+    }
+
+    /// Set how far a full pitch bend goes, in semitones.
+    pub fn set_pitch_bend_range(&self, semitones: f64) {
+        self.state.pitch_bend_range.store(semitones);
+    }
+
+    pub fn pitch_bend_range(&self) -> f64 {
+        self.state.pitch_bend_range.load()
+    }
+
+    pub fn set_channel_pressure(&self, pressure: f64) {
+        self.state.channel_pressure.store(pressure);
+    }
+
+    pub fn set_key_pressure(&self, key: u8, pressure: f64) {
+        if let Some(value) = self.state.key_pressure.get(key as usize) {
+            value.store(pressure);
+        }
+    }
+
+    pub fn set_controller(&self, controller: u8, value: f64) {
+        if let Some(slot) = self.state.controllers.get(controller as usize) {
+            slot.store(value);
+        }
+    }
+
+    /// The current value of `control`.
+    pub fn get(&self, control: Control) -> f64 {
+        let state = &self.state;
+        match control {
+            Control::PitchBend => state.pitch_bend.load() * state.pitch_bend_range.load(),
+            Control::ChannelPressure => state.channel_pressure.load(),
+            Control::KeyPressure(key) => state
+                .key_pressure
+                .get(key as usize)
+                .map_or(0.0, AtomicF64::load),
+            Control::Controller(controller) => state
+                .controllers
+                .get(controller as usize)
+                .map_or(0.0, AtomicF64::load),
+        }
+    }
+
+    /// A wave that follows `control`, e.g. `controls.wave(Control::Controller(74))`.
+    pub fn wave(&self, control: Control) -> WaveGenerator<ControlWave> {
+        ControlWave {
+            controls: self.clone(),
+            control,
+        }
+        .into()
+    }
+
+    pub fn mod_wheel(&self) -> WaveGenerator<ControlWave> {
+        self.wave(Control::Controller(CONTROLLER_MOD_WHEEL))
+    }
+
+    pub fn expression(&self) -> WaveGenerator<ControlWave> {
+        self.wave(Control::Controller(CONTROLLER_EXPRESSION))
+    }
+
+    /// A wave of `frequency`, bent by the pitch bend.
+    pub fn bent(&self, frequency: f64) -> WaveGenerator<BentFrequency> {
+        BentFrequency {
+            pitch: Glide::new(frequency).follow(),
+            controls: self.clone(),
+            expression: None,
+        }
+        .into()
+    }
+
+    /// Reset pitch bend, aftertouch and all controllers to zero. The pitch bend range stays.
+    pub fn reset(&self) {
+        let state = &self.state;
+        state.pitch_bend.store(0.0);
+        state.channel_pressure.store(0.0);
+        for value in state.key_pressure.iter().chain(state.controllers.iter()) {
+            value.store(0.0);
+        }
+    }
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Follows one control of a [`Controls`].
+#[derive(Clone)]
+pub struct ControlWave {
+    controls: Controls,
+    control: Control,
+}
+
+impl Wave for ControlWave {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        self.controls.get(self.control)
+    }
+}
+
+/// The frequency of a note, following the pitch bend of a [`Controls`] and, for expressive
+/// notes, their own pitch bend. Notes of a monophonic instrument glide to their next pitch.
+#[derive(Clone)]
+pub struct BentFrequency {
+    pitch: Gliding,
+    controls: Controls,
+    expression: Option<Controls>,
+}
+
+impl BentFrequency {
+    pub(super) fn new(glide: &Glide, controls: &Controls, expression: &Controls) -> Self {
+        Self {
+            pitch: glide.follow(),
+            controls: controls.clone(),
+            expression: Some(expression.clone()),
+        }
+    }
+}
+
+impl Wave for BentFrequency {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let bend = self.controls.get(Control::PitchBend)
+            + self
+                .expression
+                .as_ref()
+                .map_or(0.0, |expression| expression.get(Control::PitchBend));
+        self.pitch.next_frequency() * 2.0f64.powf(bend / 12.0)
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use super::controls::AtomicF64;
+use crate::effects::time_coefficient;
+
+/// How the pitch moves from one note to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlideCurve {
+    /// Evenly through the semitones in between, arriving after the portamento time.
+    Linear,
+    /// Fast at first and slowing down towards the note, covering ~63% of the way in the
+    /// portamento time.
+    Exponential,
+}
+
+/// Glide between the notes of a monophonic instrument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Portamento {
+    /// Seconds a glide takes.
+    pub time: f64,
+    pub curve: GlideCurve,
+}
+
+impl Portamento {
+    pub fn new(time: f64, curve: GlideCurve) -> Self {
+        Self { time, curve }
+    }
+}
+
+struct GlideState {
+    /// Frequency the pitch moves towards.
+    target: AtomicF64,
+    time: AtomicF64,
+    curve: AtomicU8,
+}
+
+/// The pitch of a note, which a monophonic instrument moves when it plays legato. Cloning shares
+/// the pitch.
+#[derive(Clone)]
+pub struct Glide {
+    start: f64,
+    state: Arc<GlideState>,
+}
+
+impl Glide {
+    /// A pitch that stays at `frequency` until it is told to glide.
+    pub fn new(frequency: f64) -> Self {
+        Self {
+            start: frequency,
+            state: Arc::new(GlideState {
+                target: AtomicF64::new(frequency),
+                time: AtomicF64::new(0.0),
+                curve: AtomicU8::new(GlideCurve::Linear as u8),
+            }),
+        }
+    }
+
+    /// Move the pitch to `frequency`, jumping there without `portamento`.
+    pub fn glide_to(&self, frequency: f64, portamento: Option<Portamento>) {
+        let portamento = portamento.unwrap_or(Portamento::new(0.0, GlideCurve::Linear));
+        let state = &self.state;
+        state.time.store(portamento.time);
+        state.curve.store(portamento.curve as u8, Ordering::Relaxed);
+        state.target.store(frequency);
+    }
+
+    /// The frequency the pitch is at or moving towards.
+    pub fn target(&self) -> f64 {
+        self.state.target.load()
+    }
+
+    pub(super) fn follow(&self) -> Gliding {
+        Gliding {
+            glide: self.clone(),
+            // In semitones, so glides are even across octaves.
+            current: semitones(self.start),
+            from: semitones(self.start),
+            to: semitones(self.start),
+            progress: 1.0,
+        }
+    }
+}
+
+fn semitones(frequency: f64) -> f64 {
+    12.0 * frequency.log2()
+}
+
+/// Where a voice is on its way to the target of a `Glide`.
+#[derive(Clone)]
+pub(super) struct Gliding {
+    glide: Glide,
+    current: f64,
+    from: f64,
+    to: f64,
+    /// How far a linear glide has come, between 0.0 and 1.0.
+    progress: f64,
+}
+
+impl Gliding {
+    pub(super) fn next_frequency(&mut self) -> f64 {
+        let state = &self.glide.state;
+        let target = semitones(state.target.load());
+        if target != self.to {
+            self.from = self.current;
+            self.to = target;
+            self.progress = 0.0;
+        }
+
+        let time = state.time.load();
+        self.current = if state.curve.load(Ordering::Relaxed) == GlideCurve::Exponential as u8 {
+            let coefficient = time_coefficient(time);
+            self.to + (self.current - self.to) * coefficient
+        } else {
+            self.progress = match time > 0.0 {
+                true => (self.progress + 1.0 / (time * 44100.0)).min(1.0),
+                false => 1.0,
+            };
+            self.from + (self.to - self.from) * self.progress
+        };
+        2.0f64.powf(self.current / 12.0)
+    }
+}
@@ -0,0 +1,143 @@
+use super::{default_envelope, BentFrequency, Note, Patch, VoiceTrigger};
+use crate::{
+    effects::pan_gains,
+    partial_wave::PartialWave,
+    random::Random,
+    wave::{Wave, WaveGenerator},
+    waves::{Constant, MixMul, WaveMixer, ADSR},
+};
+
+/// Plays several copies of a partial wave for every note, spread in pitch and across the stereo
+/// field, for thick supersaw-style sounds.
+///
+/// ```
+/// use rust_audio_shenanigans::instrument::{unison, PolyInstrument};
+/// use rust_audio_shenanigans::waves::sawtooth;
+///
+/// let supersaw = unison(sawtooth(), 7).with_detune(30.0).with_width(0.8);
+/// let (mut inst, wave) = PolyInstrument::new(supersaw);
+/// ```
+#[derive(Clone)]
+pub struct Unison<T> {
+    wave: T,
+    voices: usize,
+    detune: f64,
+    width: f64,
+    random_phase: bool,
+}
+
+impl<T> Unison<T> {
+    /// Distance between the lowest and the highest copy, in cents.
+    pub fn with_detune(mut self, cents: f64) -> Self {
+        self.detune = cents;
+        self
+    }
+
+    /// How far the copies spread across the stereo field, from 0.0 (all centered) to 1.0 (the
+    /// outermost copies hard left and right).
+    pub fn with_width(mut self, width: f64) -> Self {
+        self.width = width.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Start every copy at a random phase instead of all at the same one, so notes don't all
+    /// begin with the same attack.
+    pub fn with_phase_randomization(mut self, random_phase: bool) -> Self {
+        self.random_phase = random_phase;
+        self
+    }
+}
+
+/// `voices` copies of `wave` per note, 20 cents apart at the outermost copies, half spread across
+/// the stereo field and with random phases.
+pub fn unison<T>(wave: T, voices: usize) -> Unison<T> {
+    Unison {
+        wave,
+        voices: voices.max(1),
+        detune: 20.0,
+        width: 0.5,
+        random_phase: true,
+    }
+}
+
+type UnisonCopy<T> =
+    <T as PartialWave>::Target<PhaseOffset<WaveMixer<MixMul, BentFrequency, Constant>>>;
+
+impl<T> Patch for Unison<T>
+where
+    T: PartialWave + Clone,
+{
+    type Voice = WaveMixer<MixMul, UnisonWave<UnisonCopy<T>>, ADSR>;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        let mut random = Random::new();
+        // The copies are uncorrelated, so they add up in power rather than in amplitude.
+        let gain = 1.0 / (self.voices as f64).sqrt();
+
+        let copies = (0..self.voices)
+            .map(|index| {
+                // Where the copy sits between the outermost copies, from -1.0 to 1.0.
+                let position = match self.voices {
+                    1 => 0.0,
+                    voices => index as f64 / (voices - 1) as f64 * 2.0 - 1.0,
+                };
+                let ratio = 2.0f64.powf(position * self.detune / 2.0 / 1200.0);
+                let frequency = PhaseOffset {
+                    offset: if self.random_phase {
+                        random.next_f64()
+                    } else {
+                        0.0
+                    },
+                    input: (note.bent_frequency() * ratio).source,
+                };
+                let wave = (WaveGenerator::from(frequency) >> self.wave.clone()).source;
+
+                let (left, right) = pan_gains(position * self.width);
+                (wave, (gain * left, gain * right))
+            })
+            .collect();
+
+        let (adsr, trigger) = default_envelope();
+        let wave: WaveGenerator<_> = UnisonWave { copies }.into();
+        ((wave * adsr).source, trigger.into())
+    }
+}
+
+/// Frequency of a copy played by a [`Unison`], which starts its oscillator `offset` of a period
+/// ahead. Waves can't be told their phase, but one sample raised by `offset` times the sample rate
+/// moves the phase by that much, while glide and modulation of the copy stay in step with the note.
+#[derive(Clone)]
+pub struct PhaseOffset<T> {
+    offset: f64,
+    input: T,
+}
+
+impl<W: Wave> Wave for PhaseOffset<W> {
+    fn next_sample(&mut self) -> f64 {
+        let offset = std::mem::take(&mut self.offset);
+        self.input.next_sample() + offset * self.sample_rate() as f64
+    }
+}
+
+/// The copies of a note played by a [`Unison`], each with the gains of its place in the stereo
+/// field.
+#[derive(Clone)]
+pub struct UnisonWave<W> {
+    copies: Vec<(W, (f64, f64))>,
+}
+
+impl<W: Wave> Wave for UnisonWave<W> {
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.copies
+            .iter_mut()
+            .fold((0.0, 0.0), |(l, r), (wave, (gain_l, gain_r))| {
+                let sample = wave.next_sample();
+                (l + sample * *gain_l, r + sample * *gain_r)
+            })
+    }
+}
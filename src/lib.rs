@@ -1,20 +1,30 @@
 #![feature(array_chunks)]
-#![feature(type_alias_impl_trait)]
 
 use std::sync::{Arc, Mutex};
 
 use cpal::{FromSample, Sample};
 use wave::Wave;
 
+pub mod drums;
 pub mod effects;
+pub mod granular;
 pub mod instrument;
+pub mod midi;
 mod oscillator;
+mod output;
 pub mod partial_wave;
+mod random;
+mod riff;
+pub mod sampler;
+pub mod sequencer;
+pub mod soundfont;
+pub mod tuning;
 mod variable;
 pub mod wave;
 pub mod waves;
 
 pub use oscillator::Oscillator;
+pub use output::{OutputStage, OutputStats};
 pub use variable::Variable;
 
 type Generator = dyn Wave + Send;
@@ -23,6 +33,7 @@ type GeneratorArc = Arc<Mutex<Box<Generator>>>;
 pub struct WaveStreamer {
     wave_generator: GeneratorArc,
     sample_rate: Variable<u32>,
+    output_stage: Option<OutputStage>,
 }
 
 impl WaveStreamer {
@@ -34,6 +45,7 @@ impl WaveStreamer {
         Self {
             wave_generator,
             sample_rate: Variable::new(sample_rate).0,
+            output_stage: None,
         }
     }
 
@@ -41,9 +53,18 @@ impl WaveStreamer {
         Self {
             wave_generator: Arc::new(Mutex::new(Box::new(wave_generator))),
             sample_rate,
+            output_stage: None,
         }
     }
 
+    /// Run every sample through an [`OutputStage`] before handing it to the device. The returned
+    /// stats can be polled from the control thread.
+    pub fn with_output_stage(mut self) -> (Self, OutputStats) {
+        let (stage, stats) = OutputStage::new();
+        self.output_stage = Some(stage);
+        (self, stats)
+    }
+
     pub fn generate<T>(&mut self, buffer: &mut [T])
     where
         T: Sample + FromSample<f64>,
@@ -52,8 +73,17 @@ impl WaveStreamer {
         self.sample_rate.update();
 
         for [sample_l, sample_r] in buffer.array_chunks_mut() {
-            *sample_r = Sample::from_sample(gen.next_sample());
-            *sample_l = *sample_r;
+            let (mut left, mut right) = gen.next_frame();
+            if let Some(stage) = &mut self.output_stage {
+                left = stage.process(left);
+                right = stage.process(right);
+            }
+            *sample_l = Sample::from_sample(left);
+            *sample_r = Sample::from_sample(right);
+        }
+
+        if let Some(stage) = &mut self.output_stage {
+            stage.flush();
         }
     }
 }
@@ -3,40 +3,60 @@ use std::{error::Error, path::PathBuf};
 use egui_file::FileDialog;
 
 mod player;
-use player::Player;
+use player::{NoteEffects, Player};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let fname = std::env::args().nth(1).unwrap_or_default();
+    let soundfont = std::env::args().nth(2);
 
-    open_gui(fname)?;
+    open_gui(fname, soundfont)?;
     Ok(())
 }
 
-fn open_gui(fname: String) -> Result<(), eframe::Error> {
+/// Passed instead of a file name, plays live MIDI input right away.
+const LIVE_ARG: &str = "--live";
+
+fn open_gui(fname: String, soundfont: Option<String>) -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([320.0, 240.0]),
+        viewport: egui::ViewportBuilder::default().with_inner_size([400.0, 340.0]),
         ..Default::default()
     };
 
     eframe::run_native(
         "vypxl's Rust Audio Shenanigans",
         options,
-        Box::new(|_| Box::new(App::new(fname))),
+        Box::new(|_| Box::new(App::new(fname, soundfont))),
     )
 }
 
 #[derive(Default)]
 struct App {
     fname: Option<PathBuf>,
+    soundfont: Option<String>,
     open_file_dialog: Option<FileDialog>,
+    save_file_dialog: Option<FileDialog>,
     player: Option<Player>,
+    /// Used the next time playing starts.
+    effects: NoteEffects,
+    /// First and last bar (exclusive) of the loop, counting from 0.
+    loop_bars: (usize, usize),
 }
 
 impl App {
-    fn new(fname: String) -> Self {
+    fn new(fname: String, soundfont: Option<String>) -> Self {
+        if fname == LIVE_ARG {
+            let mut ret = Self {
+                soundfont,
+                ..Default::default()
+            };
+            ret.live();
+            return ret;
+        }
+
         let should_play = !fname.is_empty();
         let mut ret = Self {
             fname: Some(PathBuf::from(fname)),
+            soundfont,
             ..Default::default()
         };
 
@@ -54,13 +74,26 @@ impl App {
                 let _ = h.stop();
             }
             println!("Playing {}", fname);
-            let player = Player::from_file(fname).unwrap();
+            let player = Player::from_file(fname, self.soundfont.as_deref(), self.effects).unwrap();
             let _ = player.play();
             self.player = Some(player);
         } else {
             println!("No file selected!");
         }
     }
+
+    fn live(&mut self) {
+        if let Some(h) = self.player.take() {
+            let _ = h.stop();
+        }
+        match Player::live(self.soundfont.as_deref(), self.effects) {
+            Ok(player) => {
+                let _ = player.play();
+                self.player = Some(player);
+            }
+            Err(e) => println!("Could not listen for MIDI input: {}", e),
+        }
+    }
 }
 
 impl eframe::App for App {
@@ -107,7 +140,79 @@ impl eframe::App for App {
                             let _ = h.stop();
                         }
                     }
+                    if ui.button("Live").clicked() {
+                        self.live()
+                    }
+                    if ui.button("Save recording").clicked() && self.player.is_some() {
+                        let mut dialog = FileDialog::save_file(None);
+                        dialog.open();
+                        self.save_file_dialog = Some(dialog);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Effects on the next Play or Live:");
+                    ui.checkbox(&mut self.effects.chords, "Chords");
+                    ui.checkbox(&mut self.effects.arpeggiator, "Arpeggiator");
                 });
+
+                if let Some(dialog) = &mut self.save_file_dialog {
+                    if dialog.show(ctx).selected() {
+                        if let (Some(file), Some(player)) = (dialog.path(), &self.player) {
+                            match player.recording().save(file) {
+                                Ok(()) => println!("Saved recording to {}", file.display()),
+                                Err(e) => println!("Could not save recording: {}", e),
+                            }
+                        }
+                    }
+                }
+
+                if let Some(player) = &self.player {
+                    if let Some(transport) = player.transport() {
+                        ui.horizontal(|ui| {
+                            let paused = transport.is_paused();
+                            if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                                let _ = if paused { player.play() } else { player.stop() };
+                            }
+                            let mut position = transport.position();
+                            let seek = egui::Slider::new(&mut position, 0.0..=transport.length())
+                                .suffix(" s");
+                            if ui.add(seek).changed() {
+                                transport.seek(position);
+                            }
+                        });
+
+                        let mut speed = transport.speed();
+                        let speed_slider = egui::Slider::new(&mut speed, 0.25..=2.0).text("Speed");
+                        if ui.add(speed_slider).changed() {
+                            transport.set_speed(speed);
+                        }
+
+                        ui.horizontal(|ui| {
+                            let (start, end) = &mut self.loop_bars;
+                            let mut looping = transport.looping().is_some();
+                            let changed = ui.checkbox(&mut looping, "Loop bars").changed()
+                                | ui.add(egui::DragValue::new(start)).changed()
+                                | ui.add(egui::DragValue::new(end)).changed();
+                            if changed {
+                                let region = transport.bar(*start)..transport.bar(*end);
+                                transport.set_loop(looping.then_some(region));
+                            }
+                            if ui.button("Go to bar").clicked() {
+                                transport.seek(transport.bar(*start));
+                            }
+                        });
+                    }
+
+                    let stats = player.output_stats();
+                    ui.label(format!(
+                        "Peak: {:.2}, clipped samples: {}, invalid samples: {}",
+                        stats.peak(),
+                        stats.clipped(),
+                        stats.non_finite()
+                    ));
+                    ctx.request_repaint_after(std::time::Duration::from_millis(250));
+                }
             });
         });
     }
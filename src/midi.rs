@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+
+use midly::MidiMessage;
+
+use crate::{
+    effects::{pan_gains, time_coefficient},
+    instrument::{
+        Control, Controls, GlideCurve, Mode, Note, NotePriority, Patch, PolyInstrument,
+        PolyInstrumentWave, Portamento, SharedPatch, VoiceTrigger, CONTROLLER_EXPRESSION,
+        DEFAULT_PITCH_BEND_RANGE,
+    },
+    tuning::{Tuning, TuningMessage},
+    variable::{Variable, VariableHandle},
+    wave::{Wave, WaveGenerator},
+    waves::ADSREvent,
+};
+
+mod arpeggiator;
+mod chords;
+mod effect;
+mod recorder;
+
+pub use arpeggiator::{ArpPattern, Arpeggiator};
+pub use chords::{ChordGenerator, MAJOR_SCALE, MINOR_SCALE};
+pub use effect::{Chain, MidiEffect, NoteSink};
+pub use recorder::Recorder;
+
+/// MIDI channel 10, which General MIDI reserves for percussion.
+pub const PERCUSSION_CHANNEL: u8 = 9;
+
+/// Bank the percussion channel picks its programs from, like in SoundFonts.
+pub const PERCUSSION_BANK: u16 = 128;
+
+/// Bank select value General MIDI 2 uses to ask for drum kits on any channel.
+const BANK_SELECT_RHYTHM: u8 = 120;
+
+const CONTROLLER_BANK_SELECT: u8 = 0;
+const CONTROLLER_PORTAMENTO_TIME: u8 = 5;
+const CONTROLLER_DATA_ENTRY: u8 = 6;
+const CONTROLLER_VOLUME: u8 = 7;
+const CONTROLLER_PAN: u8 = 10;
+const CONTROLLER_BANK_SELECT_LSB: u8 = 32;
+const CONTROLLER_DATA_ENTRY_LSB: u8 = 38;
+const CONTROLLER_SUSTAIN: u8 = 64;
+const CONTROLLER_PORTAMENTO: u8 = 65;
+const CONTROLLER_SOSTENUTO: u8 = 66;
+const CONTROLLER_SOFT_PEDAL: u8 = 67;
+const CONTROLLER_NRPN_LSB: u8 = 98;
+const CONTROLLER_NRPN_MSB: u8 = 99;
+const CONTROLLER_RPN_LSB: u8 = 100;
+const CONTROLLER_RPN_MSB: u8 = 101;
+const CONTROLLER_RESET_ALL: u8 = 121;
+const CONTROLLER_MONO_MODE: u8 = 126;
+const CONTROLLER_POLY_MODE: u8 = 127;
+
+/// Glide time of the highest portamento time controller value, in seconds.
+const MAX_PORTAMENTO_TIME: f64 = 2.0;
+
+/// Registered parameter that sets the pitch bend range, in semitones and cents.
+const RPN_PITCH_BEND_RANGE: (u8, u8) = (0, 0);
+/// Registered parameter that configures an MPE zone, sent on its master channel.
+const RPN_MPE_CONFIGURATION: (u8, u8) = (0, 6);
+/// Registered parameter number that deselects parameters, so stray data entries are ignored.
+const RPN_NULL: (u8, u8) = (127, 127);
+
+/// Pitch bend range of MPE member channels until they ask for another one, in semitones.
+const MPE_MEMBER_PITCH_BEND_RANGE: f64 = 48.0;
+
+/// Time the channel volume and pan take to follow a controller, so changes don't click.
+const MIX_SMOOTHING: f64 = 0.005;
+
+/// Provides the patches a `MidiSynth` switches to on program changes.
+pub trait PatchBank {
+    /// The patch for `program` in `bank`, or `None` to keep playing the current patch. The
+    /// percussion channel asks for programs in `PERCUSSION_BANK`.
+    fn patch(&self, channel: u8, bank: u16, program: u8) -> Option<SharedPatch>;
+}
+
+/// A patch bank filled by hand. Programs that are not in the map fall back to program 0 of their
+/// bank, and then to the default patch.
+///
+/// ```
+/// use rust_audio_shenanigans::drums::DrumKit;
+/// use rust_audio_shenanigans::midi::{PatchMap, PERCUSSION_BANK};
+/// use rust_audio_shenanigans::waves::{pluck, square, triangle};
+///
+/// let bank = PatchMap::new(square())
+///     .with_patch(0, 0, triangle())
+///     // General MIDI acoustic guitar (nylon).
+///     .with_patch(0, 24, pluck(0.3, 0.7))
+///     .with_patch(PERCUSSION_BANK, 0, DrumKit::general_midi());
+/// ```
+#[derive(Clone)]
+pub struct PatchMap {
+    patches: HashMap<(u16, u8), SharedPatch>,
+    default: SharedPatch,
+}
+
+impl PatchMap {
+    pub fn new<P>(default: P) -> Self
+    where
+        P: Patch + Send + Sync + 'static,
+        P::Voice: 'static,
+    {
+        Self {
+            patches: HashMap::new(),
+            default: SharedPatch::new(default),
+        }
+    }
+
+    pub fn with_patch<P>(mut self, bank: u16, program: u8, patch: P) -> Self
+    where
+        P: Patch + Send + Sync + 'static,
+        P::Voice: 'static,
+    {
+        self.set_patch(bank, program, SharedPatch::new(patch));
+        self
+    }
+
+    pub fn set_patch(&mut self, bank: u16, program: u8, patch: SharedPatch) {
+        self.patches.insert((bank, program), patch);
+    }
+}
+
+impl PatchBank for PatchMap {
+    fn patch(&self, _channel: u8, bank: u16, program: u8) -> Option<SharedPatch> {
+        let patch = self
+            .patches
+            .get(&(bank, program))
+            .or_else(|| self.patches.get(&(bank, 0)))
+            .unwrap_or(&self.default);
+        Some(patch.clone())
+    }
+}
+
+/// Patch of channels that have not been given a program yet and the bank has nothing for.
+struct Silence;
+
+impl Patch for Silence {
+    type Voice = Vec<Box<dyn Wave + Send>>;
+
+    fn voice(&self, _note: &Note) -> (Self::Voice, VoiceTrigger) {
+        (Vec::new(), VoiceTrigger::new(Vec::new()))
+    }
+}
+
+/// Volume, expression and pan of a channel. Volume and expression are gains between 0.0 and 1.0,
+/// pan goes from -1.0 (left) to 1.0 (right).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChannelMix {
+    volume: f64,
+    expression: f64,
+    pan: f64,
+}
+
+impl Default for ChannelMix {
+    /// General MIDI defaults: volume 100, full expression, centered.
+    fn default() -> Self {
+        Self {
+            volume: controller_volume(100),
+            expression: 1.0,
+            pan: 0.0,
+        }
+    }
+}
+
+impl ChannelMix {
+    fn gains(&self) -> (f64, f64) {
+        let (left, right) = pan_gains(self.pan);
+        let gain = self.volume * self.expression;
+        (gain * left, gain * right)
+    }
+}
+
+struct Channel {
+    /// 0 - 15.
+    number: u8,
+    instrument: PolyInstrument<SharedPatch>,
+    bank: u16,
+    mix: VariableHandle<ChannelMix>,
+    /// Registered parameter data entries go to, `RPN_NULL` if none.
+    rpn: (u8, u8),
+    /// Whether the portamento switch (CC65) is on.
+    portamento: bool,
+    portamento_time: f64,
+    effect: Option<Box<dyn MidiEffect + Send>>,
+}
+
+impl Channel {
+    /// Play a note, through the effect of the channel if it has one.
+    fn play(&mut self, key: usize, event: ADSREvent, recorder: Option<&Recorder>) {
+        let (number, instrument) = (self.number, &mut self.instrument);
+        let mut out = |key, e| play_note(instrument, recorder, number, key, e);
+        match &mut self.effect {
+            Some(effect) => effect.process(key, event, &mut out),
+            None => out(key, event),
+        }
+    }
+
+    fn set_mix(&mut self, change: impl FnOnce(&mut ChannelMix)) {
+        if let Ok(mut mix) = self.mix.write() {
+            change(&mut mix);
+        }
+    }
+
+    fn update_portamento(&mut self) {
+        let portamento = Portamento::new(self.portamento_time, GlideCurve::Linear);
+        self.instrument
+            .set_portamento(self.portamento.then_some(portamento));
+    }
+
+    fn data_entry(&mut self, controller: u8, value: u8) {
+        if self.rpn != RPN_PITCH_BEND_RANGE {
+            return;
+        }
+        let controls = self.instrument.controls();
+        let range = controls.pitch_bend_range();
+        match controller {
+            CONTROLLER_DATA_ENTRY => controls.set_pitch_bend_range(value as f64),
+            _ => controls.set_pitch_bend_range(range.trunc() + value as f64 / 100.0),
+        }
+    }
+
+    /// Go back to the General MIDI defaults the channel starts with. The patch and the effect
+    /// stay.
+    fn reset(&mut self) {
+        self.reset_controllers();
+        let controls = self.instrument.controls();
+        controls.set_pitch_bend_range(DEFAULT_PITCH_BEND_RANGE);
+        controls.set_controller(CONTROLLER_VOLUME, 100.0 / 127.0);
+        controls.set_controller(CONTROLLER_PAN, 64.0 / 127.0);
+        self.set_mix(|mix| *mix = ChannelMix::default());
+        self.instrument.set_mode(Mode::Poly);
+        self.portamento_time = 0.0;
+        self.update_portamento();
+        self.bank = default_bank(self.number);
+    }
+
+    /// Reset controllers the way General MIDI asks for on "reset all controllers". Volume, pan and
+    /// the pitch bend range stay.
+    fn reset_controllers(&mut self) {
+        let controls = self.instrument.controls();
+        let volume = controls.get(Control::Controller(CONTROLLER_VOLUME));
+        let pan = controls.get(Control::Controller(CONTROLLER_PAN));
+        controls.reset();
+        controls.set_controller(CONTROLLER_VOLUME, volume);
+        controls.set_controller(CONTROLLER_PAN, pan);
+        controls.set_controller(CONTROLLER_EXPRESSION, 1.0);
+        self.set_mix(|mix| mix.expression = 1.0);
+        self.instrument.set_sustain(false);
+        self.instrument.set_sostenuto(false);
+        self.instrument.set_soft_pedal(false);
+        self.portamento = false;
+        self.update_portamento();
+        self.rpn = RPN_NULL;
+    }
+}
+
+/// An MPE zone: a master channel and the member channels next to it. Every note of the zone is
+/// played on a member channel of its own, so the pitch bend, pressure and timbre (CC74) of that
+/// channel belong to the note alone. Messages on the master channel apply to all notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MpeZone {
+    /// 0 for the lower zone, 15 for the upper zone.
+    pub master: u8,
+    pub members: u8,
+}
+
+impl MpeZone {
+    /// The zone mastered by channel 1, with members from channel 2 upwards.
+    pub fn lower(members: u8) -> Self {
+        Self {
+            master: 0,
+            members: members.min(15),
+        }
+    }
+
+    /// The zone mastered by channel 16, with members from channel 15 downwards.
+    pub fn upper(members: u8) -> Self {
+        Self {
+            master: 15,
+            members: members.min(15),
+        }
+    }
+
+    /// The zone an MPE configuration message for `members` sets up, if it was sent on a master
+    /// channel.
+    fn configured(channel: u8, members: u8) -> Option<Self> {
+        match channel {
+            0 => Some(Self::lower(members)),
+            15 => Some(Self::upper(members)),
+            _ => None,
+        }
+    }
+
+    pub fn has_member(&self, channel: u8) -> bool {
+        match self.master {
+            0 => (1..=self.members).contains(&channel),
+            _ => (self.master - self.members..self.master).contains(&channel),
+        }
+    }
+}
+
+/// Plays MIDI messages on 16 channels, each with its own `PolyInstrument`. Program changes pick
+/// the patch of a channel from a [`PatchBank`], volume (CC7), expression (CC11) and pan (CC10) mix
+/// the channels.
+///
+/// Pitch bend, aftertouch and all controllers end up in the [`Controls`] of the channel, where
+/// voices pick them up as modulation. The pitch bend range follows RPN 0 or can be set directly.
+/// Mono mode (CC126) plays a channel legato, gliding with portamento (CC65 and CC5).
+///
+/// The notes of a channel can run through a [`MidiEffect`] first. Effects that keep time, like
+/// an arpeggiator, need the source of the messages to call [`MidiSynth::advance`] as time passes.
+/// The same calls keep time for a [`Recorder`], which records what the channels play.
+///
+/// Notes on the member channels of an [`MpeZone`] play on the instrument of its master channel,
+/// with the controls of their member channel as their expression. Zones are set up by the MPE
+/// configuration message (RPN 6) or with [`MidiSynth::set_mpe_zone`].
+///
+/// ```
+/// use rust_audio_shenanigans::midi::{MidiSynth, PatchMap};
+/// use rust_audio_shenanigans::waves::triangle;
+///
+/// let (mut synth, wave) = MidiSynth::new(PatchMap::new(triangle()));
+/// synth.process(0, midly::MidiMessage::NoteOn { key: 60.into(), vel: 100.into() });
+/// ```
+pub struct MidiSynth<B> {
+    bank: B,
+    channels: Vec<Channel>,
+    zones: Vec<MpeZone>,
+    recorder: Option<Recorder>,
+}
+
+impl<B: PatchBank> MidiSynth<B> {
+    pub fn new(bank: B) -> (Self, WaveGenerator<MidiSynthWave>) {
+        let (channels, waves) = (0..16)
+            .map(|channel| {
+                let patch = bank
+                    .patch(channel, default_bank(channel), 0)
+                    .unwrap_or_else(|| SharedPatch::new(Silence));
+                let (instrument, wave) = PolyInstrument::new(patch);
+                let (mix, handle) = Variable::new_dynamic(ChannelMix::default());
+                let gains = mix.gains();
+
+                let mut state = Channel {
+                    number: channel,
+                    instrument,
+                    bank: default_bank(channel),
+                    mix: handle,
+                    rpn: RPN_NULL,
+                    portamento: false,
+                    portamento_time: 0.0,
+                    effect: None,
+                };
+                state.reset();
+
+                (
+                    state,
+                    ChannelWave {
+                        wave: wave.source,
+                        mix,
+                        gains,
+                    },
+                )
+            })
+            .unzip();
+
+        (
+            Self {
+                bank,
+                channels,
+                zones: Vec::new(),
+                recorder: None,
+            },
+            MidiSynthWave {
+                channels: waves,
+                smoothing: time_coefficient(MIX_SMOOTHING),
+            }
+            .into(),
+        )
+    }
+
+    /// Pitch bend, aftertouch and controllers of `channel`, e.g. to set its pitch bend range.
+    pub fn controls(&self, channel: u8) -> Option<&Controls> {
+        self.channels
+            .get(channel as usize)
+            .map(|state| state.instrument.controls())
+    }
+
+    /// Set up an MPE zone. A zone without members turns MPE off for its master channel, the other
+    /// zone shrinks if the two would overlap.
+    pub fn set_mpe_zone(&mut self, zone: MpeZone) {
+        self.zones.retain(|other| other.master != zone.master);
+        for other in &mut self.zones {
+            other.members = other.members.min(14u8.saturating_sub(zone.members));
+        }
+        self.zones.retain(|other| other.members > 0);
+
+        if zone.members > 0 {
+            for channel in (0..16).filter(|&channel| zone.has_member(channel)) {
+                self.channels[channel as usize]
+                    .instrument
+                    .controls()
+                    .set_pitch_bend_range(MPE_MEMBER_PITCH_BEND_RANGE);
+            }
+            self.zones.push(zone);
+        }
+    }
+
+    /// Play a note sent on an MPE member channel on the instrument of its master channel.
+    fn play_member_note(&mut self, master: u8, channel: u8, key: usize, event: ADSREvent) {
+        let expression = self.channels[channel as usize]
+            .instrument
+            .controls()
+            .clone();
+        // Every member channel has voices of its own, so two notes on the same key don't collide.
+        let instrument = &mut self.channels[master as usize].instrument;
+        match event {
+            ADSREvent::Press(velocity) => instrument.press_from(channel, key, velocity, expression),
+            ADSREvent::Release => instrument.release_from(channel, key),
+        }
+    }
+
+    /// Tune all channels with `tuning`.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        for state in &mut self.channels {
+            state.instrument.set_tuning(tuning.clone());
+        }
+    }
+
+    /// Play a system exclusive message. MIDI Tuning Standard messages retune the channels they
+    /// are meant for, other messages are ignored.
+    pub fn process_sysex(&mut self, data: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_sysex(data);
+        }
+        let Some(message) = TuningMessage::parse(data) else {
+            return;
+        };
+        for (channel, state) in self.channels.iter_mut().enumerate() {
+            if message.applies_to(channel as u8) {
+                let mut tuning = state.instrument.tuning().clone();
+                tuning.apply(&message);
+                state.instrument.set_tuning(tuning);
+            }
+        }
+    }
+
+    /// Run the notes of `channel` through `effect`, e.g. an [`Arpeggiator`]. The notes of the
+    /// previous effect are released.
+    pub fn set_effect(&mut self, channel: u8, effect: Option<Box<dyn MidiEffect + Send>>) {
+        let Some(state) = self.channels.get_mut(channel as usize) else {
+            return;
+        };
+        let (recorder, instrument) = (self.recorder.as_ref(), &mut state.instrument);
+        if let Some(old) = &mut state.effect {
+            old.stop(&mut |key, e| play_note(instrument, recorder, channel, key, e));
+        }
+        state.effect = effect;
+    }
+
+    /// Release every note on all channels at once, whether a key, a pedal or an effect holds it.
+    /// Call this whenever the source of the messages jumps, so no note is left hanging.
+    pub fn all_notes_off(&mut self) {
+        let recorder = self.recorder.as_ref();
+        for state in &mut self.channels {
+            let (number, instrument) = (state.number, &mut state.instrument);
+            if let Some(effect) = &mut state.effect {
+                effect.stop(&mut |key, e| play_note(instrument, recorder, number, key, e));
+            }
+            state.instrument.release_all();
+        }
+        if let Some(recorder) = recorder {
+            recorder.record_notes_off();
+        }
+    }
+
+    /// Release all notes and bring the channels back to how they started: program 0, default
+    /// controllers, mix and pitch bend range, and no MPE zones. Tunings and effects stay. Sources
+    /// that jump around in a song reset the synth and replay the messages up to the new position.
+    pub fn reset(&mut self) {
+        self.all_notes_off();
+        self.zones.clear();
+        for state in &mut self.channels {
+            state.reset();
+            if let Some(patch) = self.bank.patch(state.number, state.bank, 0) {
+                state.instrument.set_patch(patch);
+            }
+        }
+    }
+
+    /// Record everything that is played from now on with `recorder`: notes as the effects play
+    /// them, and all other messages as they come in.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Let `seconds` pass for the effects, which play the notes that fall into that time.
+    pub fn advance(&mut self, seconds: f64) {
+        // Step from one note of the effects to the next, so the recorder knows when they play.
+        let mut left = seconds;
+        while let Some(next) = self.next_effect_event().filter(|&next| next < left) {
+            self.advance_effects(next);
+            left -= next;
+        }
+        self.advance_effects(left);
+    }
+
+    fn advance_effects(&mut self, seconds: f64) {
+        let recorder = self.recorder.as_ref();
+        for state in &mut self.channels {
+            let (number, instrument) = (state.number, &mut state.instrument);
+            if let Some(effect) = &mut state.effect {
+                effect.advance(seconds, &mut |key, e| {
+                    play_note(instrument, recorder, number, key, e)
+                });
+            }
+        }
+        if let Some(recorder) = recorder {
+            recorder.advance(seconds);
+        }
+    }
+
+    /// Seconds until an effect plays a note on its own, to know how long the source of the
+    /// messages may wait before it calls [`MidiSynth::advance`].
+    pub fn next_effect_event(&self) -> Option<f64> {
+        self.channels
+            .iter()
+            .filter_map(|state| state.effect.as_ref()?.next_event())
+            .reduce(f64::min)
+    }
+
+    /// Tell the effects and the recorder the tempo of the song.
+    pub fn set_tempo(&mut self, seconds_per_beat: f64) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_tempo(seconds_per_beat);
+        }
+        for effect in self
+            .channels
+            .iter_mut()
+            .filter_map(|state| state.effect.as_mut())
+        {
+            effect.set_tempo(seconds_per_beat);
+        }
+    }
+
+    /// Play a message that was sent on `channel` (0 - 15).
+    pub fn process(&mut self, channel: u8, message: MidiMessage) {
+        let master = self
+            .zones
+            .iter()
+            .find(|zone| zone.has_member(channel))
+            .map(|zone| zone.master);
+        let note = matches!(
+            message,
+            MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. }
+        );
+        // Notes are recorded as they are played, after the effects. MPE notes keep their own
+        // channel.
+        if let Some(recorder) = &self.recorder {
+            if !note || master.is_some() {
+                recorder.record(channel, message);
+            }
+        }
+        if let Some(master) = master {
+            match message {
+                MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                    let event = ADSREvent::Press(vel.as_int());
+                    return self.play_member_note(master, channel, key.as_int() as usize, event);
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    let event = ADSREvent::Release;
+                    return self.play_member_note(master, channel, key.as_int() as usize, event);
+                }
+                _ => {}
+            }
+        }
+
+        let index = channel as usize;
+        let Some(state) = self.channels.get_mut(index) else {
+            return;
+        };
+        let mut zone = None;
+
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                let event = ADSREvent::Press(vel.as_int());
+                state.play(key.as_int() as usize, event, self.recorder.as_ref())
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => state.play(
+                key.as_int() as usize,
+                ADSREvent::Release,
+                self.recorder.as_ref(),
+            ),
+            MidiMessage::ProgramChange { program } => {
+                if let Some(patch) = self.bank.patch(channel, state.bank, program.as_int()) {
+                    state.instrument.set_patch(patch);
+                }
+            }
+            MidiMessage::PitchBend { bend } => {
+                state.instrument.controls().set_pitch_bend(bend.as_f64())
+            }
+            MidiMessage::ChannelAftertouch { vel } => state
+                .instrument
+                .controls()
+                .set_channel_pressure(vel.as_int() as f64 / 127.0),
+            MidiMessage::Aftertouch { key, vel } => state
+                .instrument
+                .controls()
+                .set_key_pressure(key.as_int(), vel.as_int() as f64 / 127.0),
+            MidiMessage::Controller { controller, value } => {
+                let (controller, value) = (controller.as_int(), value.as_int());
+                state
+                    .instrument
+                    .controls()
+                    .set_controller(controller, value as f64 / 127.0);
+                match controller {
+                    // General MIDI files select bank 0 on all channels, the drums stay drums.
+                    CONTROLLER_BANK_SELECT if channel == PERCUSSION_CHANNEL => {}
+                    // Banks are numbered by their MSB, like GS variations and SoundFont presets.
+                    CONTROLLER_BANK_SELECT => {
+                        state.bank = match value {
+                            BANK_SELECT_RHYTHM => PERCUSSION_BANK,
+                            bank => bank as u16,
+                        }
+                    }
+                    // The LSB only tells variations of the same bank apart, which patch banks
+                    // don't.
+                    CONTROLLER_BANK_SELECT_LSB => {}
+                    CONTROLLER_VOLUME => state.set_mix(|mix| mix.volume = controller_volume(value)),
+                    CONTROLLER_PAN => state.set_mix(|mix| mix.pan = controller_pan(value)),
+                    CONTROLLER_EXPRESSION => {
+                        state.set_mix(|mix| mix.expression = controller_volume(value))
+                    }
+                    CONTROLLER_PORTAMENTO_TIME => {
+                        state.portamento_time =
+                            MAX_PORTAMENTO_TIME * (value as f64 / 127.0).powi(2);
+                        state.update_portamento()
+                    }
+                    CONTROLLER_PORTAMENTO => {
+                        state.portamento = pedal_down(value);
+                        state.update_portamento()
+                    }
+                    CONTROLLER_MONO_MODE => state.instrument.set_mode(Mode::Mono {
+                        priority: NotePriority::Last,
+                        legato: true,
+                    }),
+                    CONTROLLER_POLY_MODE => state.instrument.set_mode(Mode::Poly),
+                    CONTROLLER_SUSTAIN => state.instrument.set_sustain(pedal_down(value)),
+                    CONTROLLER_SOSTENUTO => state.instrument.set_sostenuto(pedal_down(value)),
+                    CONTROLLER_SOFT_PEDAL => state.instrument.set_soft_pedal(pedal_down(value)),
+                    CONTROLLER_RPN_LSB => state.rpn.1 = value,
+                    CONTROLLER_RPN_MSB => state.rpn.0 = value,
+                    // Non-registered parameters are not supported, their data entries are dropped.
+                    CONTROLLER_NRPN_LSB | CONTROLLER_NRPN_MSB => state.rpn = RPN_NULL,
+                    CONTROLLER_DATA_ENTRY | CONTROLLER_DATA_ENTRY_LSB => {
+                        if state.rpn == RPN_MPE_CONFIGURATION && controller == CONTROLLER_DATA_ENTRY
+                        {
+                            zone = MpeZone::configured(channel, value);
+                        }
+                        state.data_entry(controller, value)
+                    }
+                    CONTROLLER_RESET_ALL => state.reset_controllers(),
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(zone) = zone {
+            self.set_mpe_zone(zone);
+        }
+    }
+}
+
+/// Play a note that comes out of the effect of a channel, recording it.
+fn play_note(
+    instrument: &mut PolyInstrument<SharedPatch>,
+    recorder: Option<&Recorder>,
+    channel: u8,
+    key: usize,
+    event: ADSREvent,
+) {
+    if let Some(recorder) = recorder {
+        recorder.record_note(channel, key, event);
+    }
+    instrument.play(key, event);
+}
+
+/// Bank a channel picks its programs from until a bank select says otherwise.
+fn default_bank(channel: u8) -> u16 {
+    match channel {
+        PERCUSSION_CHANNEL => PERCUSSION_BANK,
+        _ => 0,
+    }
+}
+
+/// Gain of a volume controller value, following the curve General MIDI recommends.
+fn controller_volume(value: u8) -> f64 {
+    (value as f64 / 127.0).powi(2)
+}
+
+/// Whether a pedal controller value means the pedal is down.
+fn pedal_down(value: u8) -> bool {
+    value >= 64
+}
+
+/// Pan position of a pan controller value, 64 is the center.
+fn controller_pan(value: u8) -> f64 {
+    ((value as f64 - 64.0) / 63.0).clamp(-1.0, 1.0)
+}
+
+#[derive(Clone)]
+struct ChannelWave {
+    wave: PolyInstrumentWave<SharedPatch>,
+    mix: Variable<ChannelMix>,
+    gains: (f64, f64),
+}
+
+/// Output of a `MidiSynth`, the stereo mix of all channels.
+#[derive(Clone)]
+pub struct MidiSynthWave {
+    channels: Vec<ChannelWave>,
+    smoothing: f64,
+}
+
+impl Wave for MidiSynthWave {
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        let smoothing = self.smoothing;
+        self.channels
+            .iter_mut()
+            .fold((0.0, 0.0), |(l, r), channel| {
+                let (target_l, target_r) = channel.mix.update().gains();
+                let (gain_l, gain_r) = &mut channel.gains;
+                *gain_l = target_l + (*gain_l - target_l) * smoothing;
+                *gain_r = target_r + (*gain_r - target_r) * smoothing;
+
+                let (wl, wr) = channel.wave.next_frame();
+                (l + wl * *gain_l, r + wr * *gain_r)
+            })
+    }
+}
@@ -0,0 +1,210 @@
+use super::effect::{MidiEffect, NoteSink, DEFAULT_SECONDS_PER_BEAT};
+use crate::{random::Random, waves::ADSREvent};
+
+/// Shortest step, in beats.
+const MIN_RATE: f64 = 1.0 / 16.0;
+
+/// Order an [`Arpeggiator`] plays the held keys in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpPattern {
+    Up,
+    Down,
+    /// Up and back down, without repeating the top and bottom notes.
+    UpDown,
+    /// A random held key on every step.
+    Random,
+    /// In the order the keys were pressed.
+    AsPlayed,
+}
+
+/// Plays the held keys one after another, in steps synced to the tempo of the song.
+pub struct Arpeggiator {
+    pattern: ArpPattern,
+    octaves: usize,
+    /// Length of a step in beats.
+    rate: f64,
+    /// Part of a step a note is held for.
+    gate: f64,
+    seconds_per_beat: f64,
+    /// Held keys with their velocities, in the order they were pressed.
+    held: Vec<(usize, u8)>,
+    step: usize,
+    /// The key playing now, and the seconds until it is released.
+    sounding: Option<(usize, f64)>,
+    /// Seconds until the next step, while keys are held.
+    until_step: Option<f64>,
+    random: Random,
+}
+
+impl Arpeggiator {
+    /// Up through one octave in sixteenth notes, each held for half a step.
+    pub fn new() -> Self {
+        Self {
+            pattern: ArpPattern::Up,
+            octaves: 1,
+            rate: 0.25,
+            gate: 0.5,
+            seconds_per_beat: DEFAULT_SECONDS_PER_BEAT,
+            held: Vec::new(),
+            step: 0,
+            sounding: None,
+            until_step: None,
+            random: Random::new(),
+        }
+    }
+
+    pub fn with_pattern(mut self, pattern: ArpPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Play the held keys in this many octaves, going up from the keys themselves.
+    pub fn with_octaves(mut self, octaves: usize) -> Self {
+        self.octaves = octaves.max(1);
+        self
+    }
+
+    /// Length of a step in beats, e.g. 0.5 for eighth notes. Steps are at least a 64th note long.
+    pub fn with_rate(mut self, beats: f64) -> Self {
+        self.rate = beats.max(MIN_RATE);
+        self
+    }
+
+    /// Part of a step a note is held for, between 0.0 and 1.0.
+    pub fn with_gate(mut self, gate: f64) -> Self {
+        self.gate = gate.clamp(0.0, 1.0);
+        self
+    }
+
+    fn step_length(&self) -> f64 {
+        self.rate * self.seconds_per_beat
+    }
+
+    /// The keys of one cycle through the pattern, with their velocities.
+    fn sequence(&self) -> Vec<(usize, u8)> {
+        let mut keys = self.held.clone();
+        if self.pattern != ArpPattern::AsPlayed {
+            keys.sort_unstable();
+        }
+        // Octaves above the highest MIDI key are left out.
+        let mut sequence: Vec<(usize, u8)> = (0..self.octaves)
+            .flat_map(|octave| keys.iter().map(move |&(key, vel)| (key + octave * 12, vel)))
+            .filter(|&(key, _)| key < 128)
+            .collect();
+
+        match self.pattern {
+            ArpPattern::Down => sequence.reverse(),
+            ArpPattern::UpDown if sequence.len() > 2 => {
+                let down: Vec<_> = sequence[1..sequence.len() - 1]
+                    .iter()
+                    .rev()
+                    .copied()
+                    .collect();
+                sequence.extend(down);
+            }
+            _ => {}
+        }
+        sequence
+    }
+
+    fn release(&mut self, out: &mut NoteSink) {
+        if let Some((key, _)) = self.sounding.take() {
+            out(key, ADSREvent::Release);
+        }
+    }
+
+    /// Play the next note of the pattern.
+    fn play_step(&mut self, out: &mut NoteSink) {
+        self.release(out);
+        let sequence = self.sequence();
+        if sequence.is_empty() {
+            return;
+        }
+
+        let index = match self.pattern {
+            ArpPattern::Random => (self.random.next_f64() * sequence.len() as f64) as usize,
+            _ => self.step % sequence.len(),
+        };
+        let (key, velocity) = sequence[index.min(sequence.len() - 1)];
+        self.step += 1;
+
+        out(key, ADSREvent::Press(velocity));
+        self.sounding = Some((key, self.gate * self.step_length()));
+    }
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiEffect for Arpeggiator {
+    fn process(&mut self, key: usize, event: ADSREvent, out: &mut NoteSink) {
+        match event {
+            ADSREvent::Press(velocity) => {
+                self.held.retain(|&(held, _)| held != key);
+                self.held.push((key, velocity));
+                // The first key starts the pattern right away.
+                if self.until_step.is_none() {
+                    self.step = 0;
+                    self.play_step(out);
+                    self.until_step = Some(self.step_length());
+                }
+            }
+            ADSREvent::Release => {
+                self.held.retain(|&(held, _)| held != key);
+                if self.held.is_empty() {
+                    self.release(out);
+                    self.until_step = None;
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self, seconds: f64, out: &mut NoteSink) {
+        let mut remaining = seconds;
+        while let Some(next) = self.next_event().filter(|&next| next <= remaining) {
+            remaining -= next;
+            if let Some((_, gate)) = &mut self.sounding {
+                *gate -= next;
+            }
+            if let Some(until_step) = &mut self.until_step {
+                *until_step -= next;
+            }
+
+            if matches!(self.sounding, Some((_, gate)) if gate <= 0.0) {
+                self.release(out);
+            }
+            if matches!(self.until_step, Some(until_step) if until_step <= 0.0) {
+                self.play_step(out);
+                self.until_step = self.until_step.map(|until| until + self.step_length());
+            }
+        }
+
+        if let Some((_, gate)) = &mut self.sounding {
+            *gate -= remaining;
+        }
+        if let Some(until_step) = &mut self.until_step {
+            *until_step -= remaining;
+        }
+    }
+
+    fn next_event(&self) -> Option<f64> {
+        let gate = self.sounding.map(|(_, gate)| gate.max(0.0));
+        match (gate, self.until_step) {
+            (Some(gate), Some(step)) => Some(gate.min(step.max(0.0))),
+            (gate, step) => gate.or(step.map(|step| step.max(0.0))),
+        }
+    }
+
+    fn set_tempo(&mut self, seconds_per_beat: f64) {
+        self.seconds_per_beat = seconds_per_beat;
+    }
+
+    fn stop(&mut self, out: &mut NoteSink) {
+        self.release(out);
+        self.held.clear();
+        self.until_step = None;
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use super::effect::{MidiEffect, NoteSink};
+use crate::waves::ADSREvent;
+
+pub const MAJOR_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+pub const MINOR_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+/// How a [`ChordGenerator`] builds the chord of a key.
+#[derive(Debug, Clone, PartialEq)]
+enum Voicing {
+    /// The same intervals in semitones for every key.
+    Parallel(Vec<i32>),
+    /// Steps along a scale, so chords stay in the key.
+    Diatonic {
+        tonic: u8,
+        scale: Vec<u8>,
+        degrees: Vec<i32>,
+    },
+}
+
+/// Plays a chord for every key, either with fixed intervals or as a harmonizer that stacks notes
+/// of a scale.
+///
+/// ```
+/// use rust_audio_shenanigans::midi::{ChordGenerator, MAJOR_SCALE};
+///
+/// // Power chords.
+/// let fifths = ChordGenerator::new(vec![0, 7, 12]);
+/// // Triads in C major: D plays D minor, G plays G major.
+/// let triads = ChordGenerator::diatonic(60, &MAJOR_SCALE, vec![0, 2, 4]);
+/// ```
+pub struct ChordGenerator {
+    voicing: Voicing,
+    /// The notes played for each held key.
+    chords: HashMap<usize, Vec<usize>>,
+    /// How many chords each sounding note belongs to, so chords sharing notes don't cut each
+    /// other off.
+    sounding: HashMap<usize, usize>,
+}
+
+impl ChordGenerator {
+    /// Add `intervals` (in semitones, 0 for the key itself) to every key.
+    pub fn new(intervals: Vec<i32>) -> Self {
+        Self::with_voicing(Voicing::Parallel(intervals))
+    }
+
+    /// Stack the scale `degrees` on every key (0 for the key itself, 2 for the third above),
+    /// following `scale` (semitones above the tonic) built on the `tonic` key. Keys outside the
+    /// scale are harmonized like the scale note below them.
+    pub fn diatonic(tonic: u8, scale: &[u8], degrees: Vec<i32>) -> Self {
+        let mut scale = scale.to_vec();
+        scale.sort_unstable();
+        scale.dedup();
+        if scale.is_empty() {
+            scale.push(0);
+        }
+        Self::with_voicing(Voicing::Diatonic {
+            tonic: tonic % 12,
+            scale,
+            degrees,
+        })
+    }
+
+    fn with_voicing(voicing: Voicing) -> Self {
+        Self {
+            voicing,
+            chords: HashMap::new(),
+            sounding: HashMap::new(),
+        }
+    }
+
+    fn chord(&self, key: usize) -> Vec<usize> {
+        let notes: Vec<i64> = match &self.voicing {
+            Voicing::Parallel(intervals) => intervals
+                .iter()
+                .map(|&interval| key as i64 + interval as i64)
+                .collect(),
+            Voicing::Diatonic {
+                tonic,
+                scale,
+                degrees,
+            } => {
+                let size = scale.len() as i64;
+                let relative = key as i64 - *tonic as i64;
+                let (octave, pitch_class) = (relative.div_euclid(12), relative.rem_euclid(12));
+                // The scale note at or below the key.
+                let step = scale
+                    .iter()
+                    .rposition(|&note| note as i64 <= pitch_class)
+                    .unwrap_or(0) as i64;
+
+                degrees
+                    .iter()
+                    .map(|&degree| match degree {
+                        0 => key as i64,
+                        degree => {
+                            let target = step + degree as i64;
+                            let (octaves, index) =
+                                (target.div_euclid(size), target.rem_euclid(size));
+                            *tonic as i64 + (octave + octaves) * 12 + scale[index as usize] as i64
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        let mut chord: Vec<usize> = notes
+            .into_iter()
+            .filter(|note| (0..128).contains(note))
+            .map(|note| note as usize)
+            .collect();
+        chord.sort_unstable();
+        chord.dedup();
+        chord
+    }
+}
+
+impl MidiEffect for ChordGenerator {
+    fn process(&mut self, key: usize, event: ADSREvent, out: &mut NoteSink) {
+        // A key pressed again releases its old chord first.
+        if let Some(chord) = self.chords.remove(&key) {
+            for note in chord {
+                let count = self.sounding.entry(note).or_insert(1);
+                *count -= 1;
+                if *count == 0 {
+                    self.sounding.remove(&note);
+                    out(note, ADSREvent::Release);
+                }
+            }
+        }
+
+        if let ADSREvent::Press(_) = event {
+            let chord = self.chord(key);
+            for &note in &chord {
+                let count = self.sounding.entry(note).or_insert(0);
+                *count += 1;
+                if *count == 1 {
+                    out(note, event);
+                }
+            }
+            self.chords.insert(key, chord);
+        }
+    }
+
+    fn stop(&mut self, out: &mut NoteSink) {
+        for (note, _) in self.sounding.drain() {
+            out(note, ADSREvent::Release);
+        }
+        self.chords.clear();
+    }
+}
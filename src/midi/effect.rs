@@ -0,0 +1,111 @@
+use crate::waves::ADSREvent;
+
+/// Tempo until a song sets one, 120 beats per minute.
+pub(super) const DEFAULT_SECONDS_PER_BEAT: f64 = 0.5;
+
+/// Receives the notes a `MidiEffect` plays, like `PolyInstrument::play`.
+pub type NoteSink<'a> = dyn FnMut(usize, ADSREvent) + 'a;
+
+/// Turns the notes played on an instrument into other notes, between the source of the notes and
+/// `PolyInstrument::play`. Effects that play notes on their own, like an arpeggiator, do so as
+/// time passes.
+///
+/// ```
+/// use rust_audio_shenanigans::instrument::PolyInstrument;
+/// use rust_audio_shenanigans::midi::{Arpeggiator, ChordGenerator, MidiEffect};
+/// use rust_audio_shenanigans::waves::{triangle, ADSREvent};
+///
+/// let (mut inst, wave) = PolyInstrument::new(triangle());
+/// // Play a major triad for every key and arpeggiate it.
+/// let mut effect = ChordGenerator::new(vec![0, 4, 7]).then(Arpeggiator::new());
+/// effect.process(60, ADSREvent::Press(100), &mut |key, e| inst.play(key, e));
+/// effect.advance(1.0, &mut |key, e| inst.play(key, e));
+/// ```
+pub trait MidiEffect {
+    /// Handle a key going down or up.
+    fn process(&mut self, key: usize, event: ADSREvent, out: &mut NoteSink);
+
+    /// Let `seconds` pass and play what falls into that time.
+    fn advance(&mut self, _seconds: f64, _out: &mut NoteSink) {}
+
+    /// Seconds until the effect plays something on its own, if it ever does.
+    fn next_event(&self) -> Option<f64> {
+        None
+    }
+
+    /// Follow the tempo of the song.
+    fn set_tempo(&mut self, _seconds_per_beat: f64) {}
+
+    /// Release all notes the effect is playing and forget the held keys.
+    fn stop(&mut self, out: &mut NoteSink);
+
+    /// Feed the notes of this effect into `next`.
+    fn then<E: MidiEffect>(self, next: E) -> Chain<Self, E>
+    where
+        Self: Sized,
+    {
+        Chain { first: self, next }
+    }
+}
+
+impl<E: MidiEffect + ?Sized> MidiEffect for Box<E> {
+    fn process(&mut self, key: usize, event: ADSREvent, out: &mut NoteSink) {
+        (**self).process(key, event, out)
+    }
+
+    fn advance(&mut self, seconds: f64, out: &mut NoteSink) {
+        (**self).advance(seconds, out)
+    }
+
+    fn next_event(&self) -> Option<f64> {
+        (**self).next_event()
+    }
+
+    fn set_tempo(&mut self, seconds_per_beat: f64) {
+        (**self).set_tempo(seconds_per_beat)
+    }
+
+    fn stop(&mut self, out: &mut NoteSink) {
+        (**self).stop(out)
+    }
+}
+
+/// Two effects in a row, made by [`MidiEffect::then`].
+pub struct Chain<A, B> {
+    first: A,
+    next: B,
+}
+
+impl<A: MidiEffect, B: MidiEffect> MidiEffect for Chain<A, B> {
+    fn process(&mut self, key: usize, event: ADSREvent, out: &mut NoteSink) {
+        let next = &mut self.next;
+        self.first
+            .process(key, event, &mut |key, event| next.process(key, event, out));
+    }
+
+    fn advance(&mut self, seconds: f64, out: &mut NoteSink) {
+        let next = &mut self.next;
+        self.first
+            .advance(seconds, &mut |key, event| next.process(key, event, out));
+        self.next.advance(seconds, out);
+    }
+
+    fn next_event(&self) -> Option<f64> {
+        match (self.first.next_event(), self.next.next_event()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn set_tempo(&mut self, seconds_per_beat: f64) {
+        self.first.set_tempo(seconds_per_beat);
+        self.next.set_tempo(seconds_per_beat);
+    }
+
+    fn stop(&mut self, out: &mut NoteSink) {
+        let next = &mut self.next;
+        self.first
+            .stop(&mut |key, event| next.process(key, event, out));
+        self.next.stop(out);
+    }
+}
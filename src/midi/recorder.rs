@@ -0,0 +1,331 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use midly::{
+    num::{u24, u28, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+
+use super::effect::DEFAULT_SECONDS_PER_BEAT;
+use crate::waves::ADSREvent;
+
+/// Resolution of recorded files, in ticks per beat.
+const TICKS_PER_BEAT: u16 = 480;
+
+/// Velocity of recorded note offs, which instruments don't have.
+const RELEASE_VELOCITY: u8 = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Recorded {
+    Midi {
+        channel: u8,
+        message: MidiMessage,
+    },
+    /// A system exclusive message without its leading 0xF0, ending with 0xF7.
+    SysEx(Vec<u8>),
+    /// Seconds per beat from here on.
+    Tempo(f64),
+}
+
+#[derive(Default)]
+struct Recording {
+    /// Seconds since the recording started.
+    position: f64,
+    events: Vec<(f64, Recorded)>,
+    /// Channels and keys of the notes that were pressed and not released yet.
+    sounding: HashSet<(u8, u8)>,
+    /// Seconds per beat of the last tempo change, `None` before the first.
+    tempo: Option<f64>,
+}
+
+impl Recording {
+    fn push(&mut self, event: Recorded) {
+        self.events.push((self.position, event));
+    }
+}
+
+/// Records what is played, to write it out as a Standard MIDI File. Cloning shares the
+/// recording.
+///
+/// The source of the messages keeps the time: a `MidiSynth` moves the recorder along in
+/// [`MidiSynth::advance`](super::MidiSynth::advance), a `SongPlayer` sets the position of
+/// every note it plays.
+///
+/// ```
+/// use rust_audio_shenanigans::midi::{MidiSynth, PatchMap, Recorder};
+/// use rust_audio_shenanigans::waves::triangle;
+///
+/// let recorder = Recorder::new();
+/// let (mut synth, _wave) = MidiSynth::new(PatchMap::new(triangle()));
+/// synth.set_recorder(Some(recorder.clone()));
+/// synth.process(0, midly::MidiMessage::NoteOn { key: 60.into(), vel: 100.into() });
+/// synth.advance(0.5);
+/// synth.process(0, midly::MidiMessage::NoteOff { key: 60.into(), vel: 0.into() });
+///
+/// let mut file = Vec::new();
+/// recorder.write(&mut file).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct Recorder {
+    state: Arc<Mutex<Recording>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seconds since the recording started.
+    pub fn position(&self) -> f64 {
+        self.state.lock().unwrap().position
+    }
+
+    /// Let `seconds` pass.
+    pub fn advance(&self, seconds: f64) {
+        self.state.lock().unwrap().position += seconds;
+    }
+
+    /// Move to `seconds` after the start, for sources that know when their messages happen.
+    pub fn set_position(&self, seconds: f64) {
+        self.state.lock().unwrap().position = seconds;
+    }
+
+    /// Record a message sent on `channel` (0 - 15).
+    pub fn record(&self, channel: u8, message: MidiMessage) {
+        let channel = channel.min(15);
+        let mut state = self.state.lock().unwrap();
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                state.sounding.insert((channel, key.as_int()));
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                state.sounding.remove(&(channel, key.as_int()));
+            }
+            _ => {}
+        }
+        state.push(Recorded::Midi { channel, message });
+    }
+
+    /// Record a note an instrument plays.
+    pub fn record_note(&self, channel: u8, key: usize, event: ADSREvent) {
+        let key = u7::new(key.min(127) as u8);
+        let message = match event {
+            ADSREvent::Press(velocity) => MidiMessage::NoteOn {
+                key,
+                vel: u7::new(velocity.min(127)),
+            },
+            ADSREvent::Release => MidiMessage::NoteOff {
+                key,
+                vel: u7::new(RELEASE_VELOCITY),
+            },
+        };
+        self.record(channel, message);
+    }
+
+    /// Record the release of every note that is still sounding, for when all notes stop at once.
+    pub fn record_notes_off(&self) {
+        let mut sounding: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .sounding
+            .iter()
+            .copied()
+            .collect();
+        sounding.sort_unstable();
+        for (channel, key) in sounding {
+            self.record_note(channel, key as usize, ADSREvent::Release);
+        }
+    }
+
+    /// Record a system exclusive message, with or without its leading 0xF0 and trailing 0xF7.
+    pub fn record_sysex(&self, data: &[u8]) {
+        let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+        let mut data = data.to_vec();
+        if data.last() != Some(&0xF7) {
+            data.push(0xF7);
+        }
+        self.state.lock().unwrap().push(Recorded::SysEx(data));
+    }
+
+    /// Record a change of tempo. Until the first one, the tempo is 120 beats per minute.
+    pub fn record_tempo(&self, seconds_per_beat: f64) {
+        let mut state = self.state.lock().unwrap();
+        let tempo = state.tempo.unwrap_or(DEFAULT_SECONDS_PER_BEAT);
+        if seconds_per_beat > 0.0 && seconds_per_beat != tempo {
+            state.tempo = Some(seconds_per_beat);
+            state.push(Recorded::Tempo(seconds_per_beat));
+        }
+    }
+
+    /// Throw away everything recorded and start over.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = Recording::default();
+    }
+
+    /// Write the recording as a Standard MIDI File. The first track holds the tempo and system
+    /// exclusive messages, every channel that was played on gets a track of its own.
+    pub fn write<W: io::Write>(&self, out: W) -> io::Result<()> {
+        let mut events = self.state.lock().unwrap().events.clone();
+        events.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        // Ticks follow the tempo that was recorded up to each event.
+        let mut seconds_per_beat = DEFAULT_SECONDS_PER_BEAT;
+        let (mut last_time, mut last_tick) = (0.0, 0.0);
+        let events: Vec<_> = events
+            .into_iter()
+            .map(|(time, event)| {
+                last_tick += (time - last_time) / seconds_per_beat * TICKS_PER_BEAT as f64;
+                last_time = time;
+                if let Recorded::Tempo(tempo) = event {
+                    seconds_per_beat = tempo;
+                }
+                (last_tick.round() as u32, event)
+            })
+            .collect();
+
+        let conductor = events.iter().filter_map(|(tick, event)| {
+            let kind = match event {
+                Recorded::Tempo(tempo) => {
+                    let micros = (tempo * 1_000_000.0).round().clamp(1.0, 0xFF_FFFF as f64);
+                    TrackEventKind::Meta(MetaMessage::Tempo(u24::new(micros as u32)))
+                }
+                Recorded::SysEx(data) => TrackEventKind::SysEx(data),
+                Recorded::Midi { .. } => return None,
+            };
+            Some((*tick, kind))
+        });
+        let mut tracks = vec![track(conductor)];
+
+        for number in 0..16 {
+            let channel = events.iter().filter_map(|(tick, event)| match event {
+                Recorded::Midi { channel, message } if *channel == number => {
+                    let channel = u4::new(*channel);
+                    let message = *message;
+                    Some((*tick, TrackEventKind::Midi { channel, message }))
+                }
+                _ => None,
+            });
+            let channel = track(channel);
+            // Just the end of the track.
+            if channel.len() > 1 {
+                tracks.push(channel);
+            }
+        }
+
+        let header = Header::new(Format::Parallel, Timing::Metrical(TICKS_PER_BEAT.into()));
+        let mut smf = Smf::new(header);
+        smf.tracks = tracks;
+        smf.write_std(out)
+    }
+
+    /// Write the recording to a Standard MIDI File at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+}
+
+/// A track of events at absolute ticks, in order.
+fn track<'a>(events: impl Iterator<Item = (u32, TrackEventKind<'a>)>) -> Vec<TrackEvent<'a>> {
+    let mut last = 0;
+    let mut track: Vec<_> = events
+        .map(|(tick, kind)| {
+            let delta = u28::new(tick - last);
+            last = tick;
+            TrackEvent { delta, kind }
+        })
+        .collect();
+    track.push(TrackEvent {
+        delta: 0.into(),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The events of a track at their absolute tick.
+    fn ticks<'a>(track: &[TrackEvent<'a>]) -> Vec<(u32, TrackEventKind<'a>)> {
+        let mut tick = 0;
+        track
+            .iter()
+            .map(|event| {
+                tick += event.delta.as_int();
+                (tick, event.kind)
+            })
+            .collect()
+    }
+
+    fn note(channel: u8, key: u8, on: bool) -> TrackEventKind<'static> {
+        let (key, channel) = (key.into(), channel.into());
+        let message = if on {
+            MidiMessage::NoteOn {
+                key,
+                vel: 100.into(),
+            }
+        } else {
+            MidiMessage::NoteOff {
+                key,
+                vel: RELEASE_VELOCITY.into(),
+            }
+        };
+        TrackEventKind::Midi { channel, message }
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let recorder = Recorder::new();
+        recorder.record_note(0, 60, ADSREvent::Press(100));
+        // One beat at 120 beats per minute.
+        recorder.advance(0.5);
+        recorder.record_tempo(0.25);
+        recorder.record_note(0, 60, ADSREvent::Release);
+        recorder.record_note(9, 36, ADSREvent::Press(100));
+        // Two beats at 240 beats per minute.
+        recorder.advance(0.5);
+        recorder.record_sysex(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]);
+        recorder.record_note(9, 36, ADSREvent::Release);
+
+        let mut file = Vec::new();
+        recorder.write(&mut file).unwrap();
+        let smf = Smf::parse(&file).unwrap();
+
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(smf.header.timing, Timing::Metrical(480.into()));
+        assert_eq!(smf.tracks.len(), 3);
+        let end = TrackEventKind::Meta(MetaMessage::EndOfTrack);
+        assert_eq!(
+            ticks(&smf.tracks[0]),
+            vec![
+                (
+                    480,
+                    TrackEventKind::Meta(MetaMessage::Tempo(250_000.into()))
+                ),
+                (1440, TrackEventKind::SysEx(&[0x7E, 0x7F, 0x09, 0x01, 0xF7])),
+                (1440, end),
+            ]
+        );
+        assert_eq!(
+            ticks(&smf.tracks[1]),
+            vec![
+                (0, note(0, 60, true)),
+                (480, note(0, 60, false)),
+                (480, end)
+            ]
+        );
+        assert_eq!(
+            ticks(&smf.tracks[2]),
+            vec![
+                (480, note(9, 36, true)),
+                (1440, note(9, 36, false)),
+                (1440, end),
+            ]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Level above which the output stage starts to bend the signal towards full scale.
+const SOFT_LIMIT_THRESHOLD: f64 = 0.8;
+
+#[derive(Default)]
+struct Counters {
+    clipped: AtomicU64,
+    non_finite: AtomicU64,
+    peak: AtomicU64,
+}
+
+/// Read-only view on the counters of an [`OutputStage`], meant to be polled from the control
+/// thread while the audio thread is running.
+#[derive(Clone)]
+pub struct OutputStats {
+    counters: Arc<Counters>,
+}
+
+impl OutputStats {
+    /// Number of samples whose magnitude exceeded full scale before soft limiting.
+    pub fn clipped(&self) -> u64 {
+        self.counters.clipped.load(Ordering::Relaxed)
+    }
+
+    /// Number of NaN or infinite samples that were replaced by silence.
+    pub fn non_finite(&self) -> u64 {
+        self.counters.non_finite.load(Ordering::Relaxed)
+    }
+
+    /// Highest magnitude seen before soft limiting.
+    pub fn peak(&self) -> f64 {
+        f64::from_bits(self.counters.peak.load(Ordering::Relaxed))
+    }
+
+    pub fn reset(&self) {
+        self.counters.clipped.store(0, Ordering::Relaxed);
+        self.counters.non_finite.store(0, Ordering::Relaxed);
+        self.counters
+            .peak
+            .store(0.0f64.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Last stage before the samples are handed to the device. Replaces NaN and infinities with
+/// silence and soft-limits everything else into -1.0..=1.0.
+pub struct OutputStage {
+    counters: Arc<Counters>,
+    clipped: u64,
+    non_finite: u64,
+    peak: f64,
+}
+
+impl OutputStage {
+    pub fn new() -> (Self, OutputStats) {
+        let counters = Arc::new(Counters::default());
+        (
+            Self {
+                counters: counters.clone(),
+                clipped: 0,
+                non_finite: 0,
+                peak: 0.0,
+            },
+            OutputStats { counters },
+        )
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f64) -> f64 {
+        if !sample.is_finite() {
+            self.non_finite += 1;
+            return 0.0;
+        }
+
+        let magnitude = sample.abs();
+        self.peak = self.peak.max(magnitude);
+        if magnitude > 1.0 {
+            self.clipped += 1;
+        }
+
+        if magnitude <= SOFT_LIMIT_THRESHOLD {
+            sample
+        } else {
+            let headroom = 1.0 - SOFT_LIMIT_THRESHOLD;
+            let limited = SOFT_LIMIT_THRESHOLD
+                + headroom * ((magnitude - SOFT_LIMIT_THRESHOLD) / headroom).tanh();
+            limited.copysign(sample)
+        }
+    }
+
+    /// Publish the counts gathered since the last flush. Called once per buffer, so the audio
+    /// thread doesn't touch shared memory for every single sample.
+    pub fn flush(&mut self) {
+        if self.clipped > 0 {
+            self.counters
+                .clipped
+                .fetch_add(self.clipped, Ordering::Relaxed);
+            self.clipped = 0;
+        }
+        if self.non_finite > 0 {
+            self.counters
+                .non_finite
+                .fetch_add(self.non_finite, Ordering::Relaxed);
+            self.non_finite = 0;
+        }
+        if self.peak > 0.0 {
+            let _ = self
+                .counters
+                .peak
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |peak| {
+                    (self.peak > f64::from_bits(peak)).then_some(self.peak.to_bits())
+                });
+            self.peak = 0.0;
+        }
+    }
+}
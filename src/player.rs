@@ -1,15 +1,27 @@
 // Facilities to read and play midi files using midly and cpal.
 use std::{
+    collections::BTreeMap,
     error::Error,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 use rust_audio_shenanigans::{
+    drums::DrumKit,
     effects::lowpass,
-    instrument::*,
+    midi::{
+        Arpeggiator, ChordGenerator, MidiEffect, MidiSynth, PatchBank, PatchMap, Recorder,
+        PERCUSSION_BANK, PERCUSSION_CHANNEL,
+    },
     partial_wave::{PartialWave, PartialWaveBuilder},
+    soundfont::SoundFont,
     waves::*,
     *,
 };
@@ -27,7 +39,57 @@ fn instrument() -> PartialWaveBuilder<impl PartialWave + Clone> {
     ((wave + wave2 + wave3 + wave4 + wave5 + wave6 + wave7 + wave8) * 0.2) >> lowpass(5000.0, 1.0)
 }
 
-fn setup_device() -> Result<(cpal::Device, cpal::StreamConfig), Box<dyn Error>> {
+/// Patches to play songs with when there is no soundfont. Add programs here to give the parts of a
+/// song their own sounds.
+fn patch_bank() -> PatchMap {
+    PatchMap::new(instrument()).with_patch(PERCUSSION_BANK, 0, DrumKit::general_midi())
+}
+
+/// MIDI effects to play every channel but the drums through, picked before playing starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoteEffects {
+    /// Play a major triad for every key.
+    pub chords: bool,
+    /// Arpeggiate the held keys, after the chords if both are on.
+    pub arpeggiator: bool,
+}
+
+impl NoteEffects {
+    fn effect(self) -> Option<Box<dyn MidiEffect + Send>> {
+        let chords = || ChordGenerator::new(vec![0, 4, 7]);
+        match (self.chords, self.arpeggiator) {
+            (true, true) => Some(Box::new(chords().then(Arpeggiator::new()))),
+            (true, false) => Some(Box::new(chords())),
+            (false, true) => Some(Box::new(Arpeggiator::new())),
+            (false, false) => None,
+        }
+    }
+
+    fn apply(self, synth: &mut MidiSynth<impl PatchBank>) {
+        for channel in (0..16).filter(|&channel| channel != PERCUSSION_CHANNEL) {
+            synth.set_effect(channel, self.effect());
+        }
+    }
+}
+
+/// Frames per audio buffer when playing live input. Smaller buffers lower the delay between a key
+/// press and its sound, at the risk of dropouts.
+const LIVE_BUFFER_SIZE: u32 = 256;
+
+/// Longest MIDI message taken from live input, in bytes. The longest system exclusive message the
+/// synth understands is a tuning change of 127 keys at once, a bit over 500 bytes.
+#[cfg(target_os = "linux")]
+const LIVE_MESSAGE_SIZE: usize = 1024;
+
+/// Slowest and fastest a song can be played, as a factor of its own tempo.
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 4.0;
+
+/// Open the default output device. `buffer_size` asks for a fixed number of frames per buffer,
+/// if the device supports it.
+fn setup_device(
+    buffer_size: Option<u32>,
+) -> Result<(cpal::Device, cpal::StreamConfig), Box<dyn Error>> {
     let host = cpal::default_host();
     let device = host
         .default_output_device()
@@ -37,10 +99,18 @@ fn setup_device() -> Result<(cpal::Device, cpal::StreamConfig), Box<dyn Error>>
         .next()
         .ok_or("no supported config?!")?;
 
+    let buffer_size = match (buffer_size, supported_config.buffer_size()) {
+        (Some(frames), cpal::SupportedBufferSize::Range { min, max }) => {
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max))
+        }
+        _ => cpal::BufferSize::Default,
+    };
+
     Ok((
         device,
         cpal::StreamConfig {
             channels: 2,
+            buffer_size,
             ..supported_config
                 .with_sample_rate(cpal::SampleRate(44100))
                 .config()
@@ -65,36 +135,24 @@ fn setup_stream(
     Ok(stream)
 }
 
+/// Play a MIDI event of a song. `seconds_per_beat` follows the tempo of the song, which is played
+/// `speed` times as fast.
 fn process_event(
-    event: midly::TrackEvent,
-    trigger: &mut impl FnMut(usize, ADSREvent),
-    mspt: &mut f32,
-    tpb: u32,
+    event: &midly::TrackEvent,
+    synth: &mut MidiSynth<impl PatchBank>,
+    seconds_per_beat: &mut f64,
+    speed: f64,
 ) {
     match event.kind {
-        midly::TrackEventKind::Midi { message, .. } => match message {
-            midly::MidiMessage::NoteOn { key, vel } => {
-                if vel.as_int() == 0 {
-                    // println!("off");
-                    trigger(key.as_int() as usize, ADSREvent::Release);
-                    return;
-                }
-                // println!("triggering: {key} {vel}");
-                trigger(key.as_int() as usize, ADSREvent::Press(vel.as_int()));
-            }
-            midly::MidiMessage::NoteOff { key, .. } => {
-                // todo
-                trigger(key.as_int() as usize, ADSREvent::Release);
-                // println!("off");
-            }
-            _x => {
-                // println!("midi: {:?}", _x);
-            }
-        },
+        midly::TrackEventKind::Midi { channel, message } => {
+            synth.process(channel.as_int(), message)
+        }
+        midly::TrackEventKind::SysEx(data) => synth.process_sysex(data),
         midly::TrackEventKind::Meta(meta) => match meta {
             midly::MetaMessage::Tempo(tempo) => {
-                *mspt = tempo.as_int() as f32 / (1.0 * tpb as f32);
-                // println!("tempo: {}, tpb: {}, mspt: {}", tempo, tpb, mspt);
+                *seconds_per_beat = tempo.as_int() as f64 / 1_000_000.0;
+                synth.set_tempo(*seconds_per_beat / speed);
+                // println!("tempo: {}", tempo);
             }
             midly::MetaMessage::TrackName(_name) => {
                 // println!("track name: {}", String::from_utf8_lossy(_name));
@@ -109,7 +167,425 @@ fn process_event(
     }
 }
 
-fn setup_streamer(sample_rate: u32, song: midly::Smf) -> (WaveStreamer, JoinHandle<()>) {
+/// Whether `event` starts or ends a note, which is skipped when catching up after a jump.
+fn is_note(event: &midly::TrackEvent) -> bool {
+    matches!(
+        event.kind,
+        midly::TrackEventKind::Midi {
+            message: midly::MidiMessage::NoteOn { .. } | midly::MidiMessage::NoteOff { .. },
+            ..
+        }
+    )
+}
+
+/// When every event of a song happens and where its bars start, in seconds.
+fn timeline(events: &[(u32, midly::TrackEvent)], tpb: u32) -> (Vec<f64>, Bars) {
+    let tpb = tpb as f64;
+    // 120 beats per minute in 4/4, until the song says otherwise.
+    let mut seconds_per_tick = 0.5 / tpb;
+    let mut ticks_per_bar = 4.0 * tpb;
+    let (mut last_tick, mut last_time) = (0.0, 0.0);
+    let mut bar_tick = 0.0;
+    let mut bars = vec![0.0];
+
+    let mut times = Vec::with_capacity(events.len());
+    for (tick, event) in events {
+        let tick = *tick as f64;
+        // Bars that start before the event, at the tempo up to it.
+        while bar_tick + ticks_per_bar <= tick {
+            bar_tick += ticks_per_bar;
+            bars.push(last_time + (bar_tick - last_tick) * seconds_per_tick);
+        }
+        let time = last_time + (tick - last_tick) * seconds_per_tick;
+        (last_tick, last_time) = (tick, time);
+        times.push(time);
+
+        match event.kind {
+            midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
+                seconds_per_tick = tempo.as_int() as f64 / 1_000_000.0 / tpb;
+            }
+            // A new time signature starts a new bar.
+            midly::TrackEventKind::Meta(midly::MetaMessage::TimeSignature(beats, unit, ..)) => {
+                ticks_per_bar = beats as f64 * 4.0 * tpb / 2.0f64.powi(unit as i32);
+                if tick > bar_tick {
+                    bar_tick = tick;
+                    bars.push(time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let length = ticks_per_bar * seconds_per_tick;
+    (
+        times,
+        Bars {
+            starts: bars,
+            length,
+        },
+    )
+}
+
+/// Where the bars of a song start. Bars after the end of the song keep the length of the last one.
+struct Bars {
+    starts: Vec<f64>,
+    length: f64,
+}
+
+impl Bars {
+    fn start(&self, bar: usize) -> f64 {
+        let last = self.starts.len() - 1;
+        match self.starts.get(bar) {
+            Some(start) => *start,
+            None => self.starts[last] + (bar - last) as f64 * self.length,
+        }
+    }
+}
+
+struct TransportState {
+    /// Seconds into the song, at its own tempo, when the playing thread last woke up.
+    position: f64,
+    updated: Instant,
+    paused: bool,
+    /// Position to jump to next.
+    seek: Option<f64>,
+    looping: Option<Range<f64>>,
+    speed: f64,
+    stopped: bool,
+}
+
+/// Controls the playback of a song: pause, seek, loop and speed. Cloning shares the playback.
+///
+/// Times are in seconds into the song at its own tempo, [`Transport::bar`] turns bars into times.
+/// Notes are released whenever playback pauses or jumps, and the controllers and programs of the
+/// new position are played again, so the song sounds as if it had been played up to there.
+#[derive(Clone)]
+pub struct Transport {
+    state: Arc<(Mutex<TransportState>, Condvar)>,
+    bars: Arc<Bars>,
+    length: f64,
+}
+
+impl Transport {
+    fn new(bars: Bars, length: f64) -> Self {
+        Self {
+            state: Arc::new((
+                Mutex::new(TransportState {
+                    position: 0.0,
+                    updated: Instant::now(),
+                    paused: false,
+                    seek: None,
+                    looping: None,
+                    speed: 1.0,
+                    stopped: false,
+                }),
+                Condvar::new(),
+            )),
+            bars: Arc::new(bars),
+            length,
+        }
+    }
+
+    /// Change the playback and wake up the thread playing the song to follow the change.
+    fn change(&self, change: impl FnOnce(&mut TransportState)) {
+        let (state, wake) = &*self.state;
+        change(&mut state.lock().unwrap());
+        wake.notify_all();
+    }
+
+    fn state(&self) -> MutexGuard<'_, TransportState> {
+        self.state.0.lock().unwrap()
+    }
+
+    pub fn pause(&self) {
+        let position = self.position();
+        self.change(|state| {
+            (state.position, state.updated) = (position, Instant::now());
+            state.paused = true;
+        });
+    }
+
+    pub fn resume(&self) {
+        self.change(|state| state.paused = false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// Seconds into the song.
+    pub fn position(&self) -> f64 {
+        let state = self.state();
+        match state.paused {
+            true => state.position,
+            false => state.position + state.updated.elapsed().as_secs_f64() * state.speed,
+        }
+    }
+
+    /// Length of the song in seconds, up to its last event.
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    /// The time `bar` starts at, counting from 0, e.g. `transport.seek(transport.bar(8))`.
+    pub fn bar(&self, bar: usize) -> f64 {
+        self.bars.start(bar)
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        self.change(|state| state.seek = Some(seconds.max(0.0)));
+    }
+
+    /// Jump back to the start of `region` whenever playback reaches its end, or play on with
+    /// `None`.
+    pub fn set_loop(&self, region: Option<Range<f64>>) {
+        let region = region.filter(|region| region.end > region.start);
+        self.change(|state| state.looping = region);
+    }
+
+    pub fn looping(&self) -> Option<Range<f64>> {
+        self.state().looping.clone()
+    }
+
+    /// Play `speed` times as fast as the song says, e.g. 0.5 for half the tempo.
+    pub fn set_speed(&self, speed: f64) {
+        self.change(|state| state.speed = speed.clamp(MIN_SPEED, MAX_SPEED));
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state().speed
+    }
+
+    /// Stop playing for good.
+    fn stop(&self) {
+        self.change(|state| state.stopped = true);
+    }
+}
+
+/// Controllers `chase` treats on their own.
+const DATA_ENTRY: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const DATA_INCREMENT: u8 = 96;
+const DATA_DECREMENT: u8 = 97;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+const RESET_ALL: u8 = 121;
+const MONO_MODE: u8 = 126;
+const POLY_MODE: u8 = 127;
+
+/// A parameter set through data entry: the controller selecting its MSB (`RPN_MSB` or
+/// `NRPN_MSB`, the LSB is selected by the controller below), then the MSB and LSB.
+type Parameter = (u8, u8, u8);
+
+/// Values sent with data entry MSB and LSB.
+type Data = (Option<u8>, Option<u8>);
+
+/// No parameter is selected, data entries are ignored.
+const PARAMETER_NULL: Parameter = (RPN_MSB, 127, 127);
+
+/// The state `events` leave the channels in, as the fewest messages that set it up again.
+///
+/// Every channel gets the last value of each controller, its mode, program and pitch bend. Then
+/// the parameters set through data entry, like the pitch bend range or an MPE zone, are selected
+/// and set again, in the order they were last set, as some of them change others. Last, every
+/// channel selects the parameter it had selected. Data increments are left out.
+fn chase(events: &[(u32, midly::TrackEvent)]) -> Vec<(u8, midly::MidiMessage)> {
+    use midly::MidiMessage::{Controller, PitchBend, ProgramChange};
+    let cc = |controller: u8, value: u8| Controller {
+        controller: controller.into(),
+        value: value.into(),
+    };
+
+    let mut settings = BTreeMap::new();
+    let mut selected: BTreeMap<u8, Parameter> = BTreeMap::new();
+    let mut parameters: Vec<((u8, Parameter), Data)> = Vec::new();
+
+    for (_, event) in events {
+        let midly::TrackEventKind::Midi { channel, message } = event.kind else {
+            continue;
+        };
+        let channel = channel.as_int();
+        let setting = match message {
+            Controller { controller, value } => {
+                let (controller, value) = (controller.as_int(), value.as_int());
+                match controller {
+                    RPN_MSB | RPN_LSB | NRPN_MSB | NRPN_LSB => {
+                        let kind = if controller >= RPN_LSB {
+                            RPN_MSB
+                        } else {
+                            NRPN_MSB
+                        };
+                        let (_, msb, lsb) = *selected.get(&channel).unwrap_or(&PARAMETER_NULL);
+                        let parameter = match controller == kind {
+                            true => (kind, value, lsb),
+                            false => (kind, msb, value),
+                        };
+                        selected.insert(channel, parameter);
+                        continue;
+                    }
+                    DATA_ENTRY | DATA_ENTRY_LSB => {
+                        let parameter = *selected.get(&channel).unwrap_or(&PARAMETER_NULL);
+                        if parameter == PARAMETER_NULL {
+                            continue;
+                        }
+                        let set = (channel, parameter);
+                        let (mut data, mut data_lsb) =
+                            match parameters.iter().position(|p| p.0 == set) {
+                                Some(index) => parameters.remove(index).1,
+                                None => (None, None),
+                            };
+                        match controller {
+                            DATA_ENTRY => data = Some(value),
+                            _ => data_lsb = Some(value),
+                        }
+                        parameters.push((set, (data, data_lsb)));
+                        continue;
+                    }
+                    DATA_INCREMENT | DATA_DECREMENT => continue,
+                    // Controllers go back to their defaults, all but the bank, volume and pan.
+                    RESET_ALL => {
+                        settings.retain(|&(other, setting), _| {
+                            other != channel || matches!(setting, 0 | 7 | 10 | 32 | 126 | 128)
+                        });
+                        selected.remove(&channel);
+                        continue;
+                    }
+                    MONO_MODE | POLY_MODE => MONO_MODE as u16,
+                    // The other channel mode messages don't leave anything behind.
+                    120.. => continue,
+                    controller => controller as u16,
+                }
+            }
+            ProgramChange { .. } => 128,
+            PitchBend { .. } => 129,
+            _ => continue,
+        };
+        settings.insert((channel, setting), message);
+    }
+
+    let mut messages: Vec<_> = settings
+        .into_iter()
+        .map(|((channel, _), message)| (channel, message))
+        .collect();
+    let select = |channel, (kind, msb, lsb): Parameter| {
+        [(channel, cc(kind, msb)), (channel, cc(kind - 1, lsb))]
+    };
+    for ((channel, parameter), (data, data_lsb)) in parameters {
+        messages.extend(select(channel, parameter));
+        messages.extend(data.map(|value| (channel, cc(DATA_ENTRY, value))));
+        messages.extend(data_lsb.map(|value| (channel, cc(DATA_ENTRY_LSB, value))));
+    }
+    for (channel, parameter) in selected {
+        messages.extend(select(channel, parameter));
+    }
+    messages
+}
+
+/// Play `events` with their `times` on `synth`, as `transport` says.
+fn play_song(
+    events: Vec<(u32, midly::TrackEvent)>,
+    times: Vec<f64>,
+    mut synth: MidiSynth<impl PatchBank>,
+    transport: Transport,
+) {
+    let (lock, wake) = &*transport.state;
+    let mut next = 0;
+    let mut position = 0.0;
+    let mut seconds_per_beat = 0.5;
+    let (mut paused, mut speed) = (false, 1.0);
+    let mut last = Instant::now();
+
+    let mut state = lock.lock().unwrap();
+    loop {
+        if state.stopped {
+            break;
+        }
+
+        // Time only passes while playing.
+        let now = Instant::now();
+        if !paused {
+            let elapsed = (now - last).as_secs_f64();
+            synth.advance(elapsed);
+            position += elapsed * speed;
+        }
+        last = now;
+
+        if state.paused && !paused {
+            synth.all_notes_off();
+        }
+        if state.speed != speed {
+            synth.set_tempo(seconds_per_beat / state.speed);
+        }
+        (paused, speed) = (state.paused, state.speed);
+
+        let looped = match &state.looping {
+            Some(region) if position >= region.end && !paused => Some(region.start),
+            _ => None,
+        };
+        if let Some(target) = state.seek.take().or(looped) {
+            // Start over and play everything but the notes up to the new position. Catching up
+            // isn't recorded, only the notes that stop and the state the channels end up in.
+            synth.all_notes_off();
+            let recorder = synth.recorder().cloned();
+            synth.set_recorder(None);
+            synth.reset();
+            seconds_per_beat = 0.5;
+            synth.set_tempo(seconds_per_beat / speed);
+            next = times.partition_point(|&time| time < target);
+            for (_, event) in events[..next].iter().filter(|(_, event)| !is_note(event)) {
+                process_event(event, &mut synth, &mut seconds_per_beat, speed);
+            }
+            synth.set_recorder(recorder.clone());
+            synth.set_tempo(seconds_per_beat / speed);
+            if let Some(recorder) = recorder {
+                for (_, event) in &events[..next] {
+                    if let midly::TrackEventKind::SysEx(data) = event.kind {
+                        recorder.record_sysex(data);
+                    }
+                }
+                for (channel, message) in chase(&events[..next]) {
+                    recorder.record(channel, message);
+                }
+            }
+            position = target;
+        }
+        (state.position, state.updated) = (position, now);
+
+        if paused {
+            state = wake.wait(state).unwrap();
+            continue;
+        }
+
+        while next < events.len() && times[next] <= position {
+            process_event(&events[next].1, &mut synth, &mut seconds_per_beat, speed);
+            next += 1;
+        }
+
+        // Sleep until the next event, the end of the loop or a note of an effect, unless the
+        // transport changes before.
+        let until_event = times.get(next).map(|time| (time - position) / speed);
+        let until_loop = state
+            .looping
+            .as_ref()
+            .map(|region| (region.end - position).max(0.0) / speed);
+        let timeout = [until_event, until_loop, synth.next_effect_event()]
+            .into_iter()
+            .flatten()
+            .reduce(f64::min);
+        state = match timeout {
+            Some(timeout) => {
+                let timeout = Duration::from_secs_f64(timeout.max(0.0));
+                wake.wait_timeout(state, timeout).unwrap().0
+            }
+            None => wake.wait(state).unwrap(),
+        };
+    }
+}
+
+/// The events of all tracks of `song` at their tick, in order, and the ticks per beat.
+fn song_events<'a>(song: &midly::Smf<'a>) -> (Vec<(u32, midly::TrackEvent<'a>)>, u32) {
     let tpb = match song.header.timing {
         midly::Timing::Metrical(x) => x.as_int() as u32,
         midly::Timing::Timecode(_, _) => todo!(),
@@ -117,76 +593,289 @@ fn setup_streamer(sample_rate: u32, song: midly::Smf) -> (WaveStreamer, JoinHand
 
     let mut all_events = Vec::new();
 
-    for track in song.to_static().tracks.into_iter() {
+    for track in song.tracks.iter() {
         let mut cursor = 0;
-        for event in track.into_iter() {
+        for event in track.iter() {
             cursor += event.delta.as_int();
-            all_events.push((cursor, event));
+            all_events.push((cursor, *event));
         }
     }
 
     // Sort all events by their timestamp.
     all_events.sort_by_key(|(timestamp, _)| *timestamp);
 
-    let p = instrument();
-    let (mut inst, wave) = PolyInstrument::new(p);
+    (all_events, tpb)
+}
+
+/// Play the MIDI file in `data` on a `MidiSynth`, which takes the patch of each channel from
+/// `bank` and runs the notes through `effects`. What is played goes to `recorder`.
+fn setup_streamer(
+    sample_rate: u32,
+    data: Vec<u8>,
+    bank: impl PatchBank + Send + 'static,
+    effects: NoteEffects,
+    recorder: Recorder,
+) -> Result<(WaveStreamer, JoinHandle<()>, Transport), midly::Error> {
+    let (times, bars) = {
+        let (events, tpb) = song_events(&midly::Smf::parse(&data)?);
+        timeline(&events, tpb)
+    };
+    let transport = Transport::new(bars, times.last().copied().unwrap_or(0.0));
+
+    let (mut synth, wave) = MidiSynth::new(bank);
+    effects.apply(&mut synth);
+    synth.set_recorder(Some(recorder));
+    let wave = wave * 0.1;
+
+    // Process all events in order. System exclusive messages borrow from the file, so the thread
+    // that plays them parses it again.
+    let handle = {
+        let transport = transport.clone();
+        thread::spawn(move || {
+            if let Ok(song) = midly::Smf::parse(&data) {
+                play_song(song_events(&song).0, times, synth, transport)
+            }
+        })
+    };
+
+    Ok((WaveStreamer::new(wave, sample_rate), handle, transport))
+}
+
+/// Play the MIDI messages sent to a virtual ALSA sequencer port on a `MidiSynth`, until `running`
+/// is cleared. Other programs and keyboards connect to the port, e.g. with `aconnect`.
+#[cfg(target_os = "linux")]
+fn setup_live_streamer(
+    sample_rate: u32,
+    bank: impl PatchBank + Send + 'static,
+    effects: NoteEffects,
+    recorder: Recorder,
+    running: Arc<AtomicBool>,
+) -> Result<(WaveStreamer, JoinHandle<()>), Box<dyn Error>> {
+    use alsa::{
+        poll::Descriptors,
+        seq::{MidiEvent, PortCap, PortType, Seq},
+        Direction,
+    };
+    use std::ffi::CString;
+
+    let name = CString::new("Rust Audio Shenanigans")?;
+    let seq = Seq::open(None, Some(Direction::Capture), true)?;
+    seq.set_client_name(&name)?;
+    seq.create_simple_port(
+        &CString::new("MIDI in")?,
+        PortCap::WRITE | PortCap::SUBS_WRITE,
+        PortType::MIDI_GENERIC | PortType::APPLICATION,
+    )?;
+    println!("Listening for MIDI on ALSA client {}", seq.client_id()?);
+
+    let mut fds = Descriptors::get(&(&seq, Some(Direction::Capture)))?;
+
+    let (mut synth, wave) = MidiSynth::new(bank);
+    effects.apply(&mut synth);
+    synth.set_recorder(Some(recorder));
     let wave = wave * 0.1;
 
-    // Process all events in order.
     let handle = thread::spawn(move || {
-        let mut last_timestamp = 0;
-        let mut mspt = 0.0;
-        for (timestamp, event) in all_events {
-            let delta = (timestamp - last_timestamp) as f32;
-            let sleeptime = (delta * mspt) as u64;
-            if sleeptime > 0 {
-                thread::sleep(std::time::Duration::from_micros((delta * mspt) as u64))
+        // Sequencer events are turned back into raw messages, which midly understands.
+        let Ok(decoder) = MidiEvent::new(0) else {
+            return;
+        };
+        decoder.enable_running_status(false);
+        let mut buffer = [0; LIVE_MESSAGE_SIZE];
+        let mut input = seq.input();
+        let mut last = Instant::now();
+
+        while running.load(Ordering::Relaxed) {
+            // Wake up now and then to notice when the player is gone.
+            let ready = match alsa::poll::poll(&mut fds, 100) {
+                Ok(ready) => ready,
+                Err(err) if is_interrupted(&err) => continue,
+                Err(err) => {
+                    // Polling again would fail the same way, without ever waiting.
+                    eprintln!("stopped listening for MIDI: {}", err);
+                    break;
+                }
+            };
+            // Messages arrive as they are played, the clock of the synth follows the wall clock.
+            let now = Instant::now();
+            synth.advance((now - last).as_secs_f64());
+            last = now;
+            if ready == 0 {
+                continue;
+            }
+
+            while input.event_input_pending(true).unwrap_or(0) > 0 {
+                let Ok(mut event) = input.event_input() else {
+                    break;
+                };
+                let Ok(len) = decoder.decode(&mut buffer, &mut event) else {
+                    continue;
+                };
+                match midly::live::LiveEvent::parse(&buffer[..len]) {
+                    Ok(midly::live::LiveEvent::Midi { channel, message }) => {
+                        synth.process(channel.as_int(), message)
+                    }
+                    Ok(midly::live::LiveEvent::Common(midly::live::SystemCommon::SysEx(data))) => {
+                        synth.process_sysex(midly::num::u7::slice_as_int(data))
+                    }
+                    _ => {}
+                }
             }
-            process_event(event, &mut |key, e| inst.play(key, e), &mut mspt, tpb);
-            last_timestamp = timestamp;
         }
     });
 
-    (WaveStreamer::new(wave, sample_rate), handle)
+    Ok((WaveStreamer::new(wave, sample_rate), handle))
+}
+
+/// Whether a signal interrupted the call that failed with `err`, so it can just be retried.
+#[cfg(target_os = "linux")]
+fn is_interrupted(err: &alsa::Error) -> bool {
+    err.errno() == alsa::nix::errno::Errno::EINTR
 }
 
 pub struct Player {
     stream: cpal::Stream,
+    output_stats: OutputStats,
     _thread_handle: Option<JoinHandle<()>>,
+    /// Cleared when the player is dropped, to stop listening for live input.
+    running: Arc<AtomicBool>,
+    recorder: Recorder,
+    /// Playback of a song, live input has none.
+    transport: Option<Transport>,
 }
 
 impl Player {
-    pub fn new(stream: cpal::Stream, thread_handle: JoinHandle<()>) -> Self {
+    pub fn new(
+        stream: cpal::Stream,
+        output_stats: OutputStats,
+        thread_handle: JoinHandle<()>,
+    ) -> Self {
         Self {
             stream,
+            output_stats,
             _thread_handle: Some(thread_handle),
+            running: Arc::new(AtomicBool::new(true)),
+            recorder: Recorder::new(),
+            transport: None,
         }
     }
 
-    pub fn from_file(fname: &str) -> Result<Self, Box<dyn Error>> {
-        let (device, config) = setup_device()?;
+    /// Play the midi file `fname`. Instruments come from the `soundfont`, if there is one, and
+    /// follow the program changes of the song. The notes are played through `effects`.
+    pub fn from_file(
+        fname: &str,
+        soundfont: Option<&str>,
+        effects: NoteEffects,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (device, config) = setup_device(None)?;
 
         let data = std::fs::read(fname)?;
-        let smf = midly::Smf::parse(&data)?;
-        let (streamer, handle) = setup_streamer(config.sample_rate.0, smf);
+        let sample_rate = config.sample_rate.0;
+        let recorder = Recorder::new();
+        let (streamer, handle, transport) = match soundfont {
+            Some(soundfont) => setup_streamer(
+                sample_rate,
+                data,
+                SoundFont::from_file(soundfont)?,
+                effects,
+                recorder.clone(),
+            ),
+            None => setup_streamer(sample_rate, data, patch_bank(), effects, recorder.clone()),
+        }?;
+        let (streamer, output_stats) = streamer.with_output_stage();
 
         let stream = setup_stream(&device, &config, streamer)?;
 
-        Ok(Player::new(stream, handle))
+        let mut player = Player::new(stream, output_stats, handle);
+        player.recorder = recorder;
+        player.transport = Some(transport);
+        Ok(player)
     }
 
+    /// Play MIDI input from a virtual sequencer port, on instruments from the `soundfont` if there
+    /// is one, and through `effects`. Audio is rendered in small buffers to keep the latency low.
+    #[cfg(target_os = "linux")]
+    pub fn live(soundfont: Option<&str>, effects: NoteEffects) -> Result<Self, Box<dyn Error>> {
+        let (device, config) = setup_device(Some(LIVE_BUFFER_SIZE))?;
+
+        let sample_rate = config.sample_rate.0;
+        let running = Arc::new(AtomicBool::new(true));
+        let recorder = Recorder::new();
+        let (streamer, handle) = match soundfont {
+            Some(soundfont) => setup_live_streamer(
+                sample_rate,
+                SoundFont::from_file(soundfont)?,
+                effects,
+                recorder.clone(),
+                running.clone(),
+            ),
+            None => setup_live_streamer(
+                sample_rate,
+                patch_bank(),
+                effects,
+                recorder.clone(),
+                running.clone(),
+            ),
+        }?;
+        let (streamer, output_stats) = streamer.with_output_stage();
+
+        let stream = setup_stream(&device, &config, streamer)?;
+
+        let mut player = Player::new(stream, output_stats, handle);
+        player.running = running;
+        player.recorder = recorder;
+        Ok(player)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn live(_soundfont: Option<&str>, _effects: NoteEffects) -> Result<Self, Box<dyn Error>> {
+        Err("live MIDI input needs the ALSA sequencer".into())
+    }
+
+    /// Start or resume playing.
     pub fn play(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(transport) = &self.transport {
+            transport.resume();
+        }
         self.stream.play()?;
         Ok(())
     }
+
+    /// Pause playing. Songs stop where they are and go on from there with `play`.
     pub fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(transport) = &self.transport {
+            transport.pause();
+        }
         self.stream.pause()?;
         Ok(())
     }
 
+    pub fn output_stats(&self) -> &OutputStats {
+        &self.output_stats
+    }
+
+    /// Everything played so far, after program changes, effects and the like.
+    pub fn recording(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    /// Seeking, looping and speed of the song, if a song is playing.
+    pub fn transport(&self) -> Option<&Transport> {
+        self.transport.as_ref()
+    }
+
     // fn wait(&mut self) {
     //     if let Some(h) = self._thread_handle.take() {
     //         h.join().unwrap();
     //     }
     // }
 }
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(transport) = &self.transport {
+            transport.stop();
+        }
+    }
+}
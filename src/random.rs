@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Seeds handed out to new generators, so that no two of them produce the same sequence.
+static SEED: AtomicU64 = AtomicU64::new(0x853c_49e6_748f_ea9b);
+
+/// Small xorshift generator for randomized parameters. Not suited for anything but audio.
+#[derive(Clone)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        Self::with_seed(SEED.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed))
+    }
+
+    /// A generator that always produces the same sequence for the same `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        // Spread consecutive seeds with the splitmix64 finalizer.
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Self {
+            state: (z ^ (z >> 31)).max(1),
+        }
+    }
+
+    /// Uniformly distributed between 0.0 and 1.0.
+    pub fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniformly distributed between -1.0 and 1.0.
+    pub fn bipolar(&mut self) -> f64 {
+        self.next_f64() * 2.0 - 1.0
+    }
+}
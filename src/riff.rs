@@ -0,0 +1,57 @@
+/// A chunk of a RIFF file: its four character id and its contents.
+pub(crate) struct Chunk<'a> {
+    pub id: &'a [u8],
+    pub data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    /// For `LIST` chunks, the list type and the chunks it contains.
+    pub fn list(&self) -> Option<(&'a [u8], Vec<Chunk<'a>>)> {
+        Some((self.data.get(0..4)?, chunks(&self.data[4..])))
+    }
+}
+
+/// Read the form type and the top level chunks of a RIFF file.
+pub(crate) fn parse(bytes: &[u8]) -> Option<(&[u8], Vec<Chunk<'_>>)> {
+    if bytes.get(0..4)? != b"RIFF" {
+        return None;
+    }
+    Some((bytes.get(8..12)?, chunks(&bytes[12..])))
+}
+
+/// Split `data` into consecutive chunks. A truncated last chunk is cut off at the end of the data.
+pub(crate) fn chunks(data: &[u8]) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+
+    while let (Some(id), Some(size)) = (data.get(offset..offset + 4), u32_at(data, offset + 4)) {
+        let start = offset + 8;
+        let end = (start + size as usize).min(data.len());
+        chunks.push(Chunk {
+            id,
+            data: &data[start..end],
+        });
+        // Chunks are padded to an even size.
+        offset = start + size as usize + (size as usize & 1);
+    }
+
+    chunks
+}
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    Some(i16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
@@ -0,0 +1,102 @@
+use std::{path::Path, sync::Arc};
+
+mod instrument;
+mod player;
+mod wav;
+
+pub use instrument::{Sampler, Zone, ZoneVoice};
+pub use player::{sample_player, PartialSamplePlayer, SamplePlayer};
+pub use wav::WavError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+    /// Play the buffer once and stay silent afterwards.
+    OneShot,
+    /// Jump back to the loop start whenever the loop end is reached.
+    Forward,
+    /// Bounce back and forth between loop start and loop end.
+    PingPong,
+}
+
+/// Decoded audio together with the settings needed to play it back. The samples are shared, so
+/// cloning a buffer for every voice is cheap.
+#[derive(Clone)]
+pub struct SampleBuffer {
+    samples: Arc<[f64]>,
+    pub channels: usize,
+    pub sample_rate: u32,
+    /// MIDI note the buffer sounds at when played at its original speed. Fractional values tune
+    /// the buffer in cents.
+    pub root_key: f64,
+    pub loop_mode: LoopMode,
+    /// First frame of the loop.
+    pub loop_start: usize,
+    /// Frame after the last frame of the loop.
+    pub loop_end: usize,
+    /// Frame playback starts at.
+    pub start: usize,
+}
+
+impl SampleBuffer {
+    /// Wrap interleaved `samples` with the given number of `channels`, recorded at `sample_rate`.
+    pub fn new(samples: Vec<f64>, channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let frames = samples.len() / channels;
+        Self {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+            root_key: 60.0,
+            loop_mode: LoopMode::OneShot,
+            loop_start: 0,
+            loop_end: frames,
+            start: 0,
+        }
+    }
+
+    /// Decode a WAV file. PCM with 8, 16, 24 and 32 bits as well as 32 and 64 bit float are
+    /// supported. Root key and loop points are taken from the `smpl` chunk, if the file has one.
+    pub fn from_wav_file<P: AsRef<Path>>(path: P) -> Result<Self, WavError> {
+        Self::from_wav(&std::fs::read(path)?)
+    }
+
+    pub fn from_wav(bytes: &[u8]) -> Result<Self, WavError> {
+        wav::parse_wav(bytes)
+    }
+
+    pub fn with_root_key(mut self, root_key: f64) -> Self {
+        self.root_key = root_key;
+        self
+    }
+
+    pub fn with_loop(mut self, loop_mode: LoopMode, loop_start: usize, loop_end: usize) -> Self {
+        self.loop_mode = loop_mode;
+        self.loop_start = loop_start;
+        self.loop_end = loop_end;
+        self
+    }
+
+    pub fn with_start(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Number of frames, i.e. samples per channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// The loop points, clamped to the buffer.
+    fn loop_range(&self) -> (usize, usize) {
+        let end = self.loop_end.clamp(1, self.frames().max(1));
+        (self.loop_start.min(end - 1), end)
+    }
+
+    #[inline]
+    pub(crate) fn frame(&self, index: usize, channel: usize) -> f64 {
+        self.samples
+            .get(index * self.channels + channel)
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
@@ -0,0 +1,175 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    effects::db_to_gain,
+    instrument::{BentFrequency, Note, Patch, VoiceTrigger},
+    sampler::{SampleBuffer, SamplePlayer},
+    wave::Wave,
+    waves::ADSR,
+};
+
+/// A sample together with the keys and velocities it is played for.
+#[derive(Clone)]
+pub struct Zone {
+    pub buffer: SampleBuffer,
+    pub keys: RangeInclusive<u8>,
+    pub velocities: RangeInclusive<u8>,
+    /// Detune in cents, on top of the root key of the buffer.
+    pub fine_tune: f64,
+    /// Volume in dB.
+    pub volume: f64,
+    /// Balance between -1.0 (left) and 1.0 (right). The other channel is attenuated linearly, so
+    /// centered zones play at full volume.
+    pub pan: f64,
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f64,
+    pub release: f64,
+}
+
+impl Zone {
+    /// A zone that covers all keys and velocities, with an envelope that plays the sample as is.
+    pub fn new(buffer: SampleBuffer) -> Self {
+        Self {
+            buffer,
+            keys: 0..=127,
+            velocities: 1..=127,
+            fine_tune: 0.0,
+            volume: 0.0,
+            pan: 0.0,
+            attack: 0.001,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.1,
+        }
+    }
+
+    pub fn with_keys(mut self, keys: RangeInclusive<u8>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_velocities(mut self, velocities: RangeInclusive<u8>) -> Self {
+        self.velocities = velocities;
+        self
+    }
+
+    pub fn with_fine_tune(mut self, fine_tune: f64) -> Self {
+        self.fine_tune = fine_tune;
+        self
+    }
+
+    pub fn with_volume(mut self, volume: f64) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn with_pan(mut self, pan: f64) -> Self {
+        self.pan = pan;
+        self
+    }
+
+    pub fn with_envelope(mut self, attack: f64, decay: f64, sustain: f64, release: f64) -> Self {
+        self.attack = attack;
+        self.decay = decay;
+        self.sustain = sustain;
+        self.release = release;
+        self
+    }
+
+    /// Gains of the left and the right channel.
+    fn gains(&self) -> (f64, f64) {
+        let gain = db_to_gain(self.volume);
+        let pan = self.pan.clamp(-1.0, 1.0);
+        (gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0))
+    }
+
+    fn matches(&self, note: &Note) -> bool {
+        self.keys.contains(&(note.key.min(127) as u8)) && self.velocities.contains(&note.velocity)
+    }
+}
+
+/// One zone sounding for one note.
+pub struct ZoneVoice {
+    player: SamplePlayer<BentFrequency>,
+    envelope: ADSR,
+    gain: (f64, f64),
+}
+
+impl Wave for ZoneVoice {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (l, r) = self.player.next_frame();
+        let envelope = self.envelope.next_sample();
+        (l * envelope * self.gain.0, r * envelope * self.gain.1)
+    }
+}
+
+/// Multi-sample instrument. Every note plays all zones that cover its key and velocity, so zones
+/// can be layered, e.g. a left and a right channel sample or a soft and a hard hit.
+///
+/// ```no_run
+/// use rust_audio_shenanigans::instrument::PolyInstrument;
+/// use rust_audio_shenanigans::sampler::{SampleBuffer, Sampler, Zone};
+///
+/// let low = SampleBuffer::from_wav_file("piano_c3.wav").unwrap().with_root_key(48.0);
+/// let high = SampleBuffer::from_wav_file("piano_c5.wav").unwrap().with_root_key(72.0);
+///
+/// let sampler = Sampler::new(vec![
+///     Zone::new(low).with_keys(0..=59),
+///     Zone::new(high).with_keys(60..=127).with_envelope(0.001, 0.0, 1.0, 0.3),
+/// ]);
+/// let (mut inst, wave) = PolyInstrument::new(sampler);
+/// ```
+#[derive(Clone, Default)]
+pub struct Sampler {
+    zones: Vec<Zone>,
+}
+
+impl Sampler {
+    pub fn new(zones: Vec<Zone>) -> Self {
+        Self { zones }
+    }
+
+    pub fn add_zone(&mut self, zone: Zone) {
+        self.zones.push(zone);
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+}
+
+impl Patch for Sampler {
+    type Voice = Vec<ZoneVoice>;
+
+    fn voice(&self, note: &Note) -> (Self::Voice, VoiceTrigger) {
+        let (voices, triggers) = self
+            .zones
+            .iter()
+            .filter(|zone| zone.matches(note))
+            .map(|zone| {
+                let buffer = zone
+                    .buffer
+                    .clone()
+                    .with_root_key(zone.buffer.root_key - zone.fine_tune / 100.0);
+                let (envelope, trigger) =
+                    ADSR::new(zone.attack, zone.decay, zone.sustain, zone.release);
+                let voice = ZoneVoice {
+                    player: SamplePlayer::new(buffer, note.bent_frequency().source).source,
+                    envelope: envelope.source,
+                    gain: zone.gains(),
+                };
+                (voice, trigger)
+            })
+            .unzip();
+
+        (voices, VoiceTrigger::new(triggers))
+    }
+}
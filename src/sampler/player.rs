@@ -0,0 +1,115 @@
+use crate::{
+    instrument::midi_note_number_to_frequency,
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    sampler::{LoopMode, SampleBuffer},
+    wave::{Wave, WaveGenerator},
+};
+
+/// Plays a [`SampleBuffer`]. The input is the frequency to play it at, so the buffer plays at its
+/// original speed when the input matches the frequency of its root key. That makes it a drop-in
+/// replacement for an oscillator, e.g. inside a `PolyInstrument`.
+#[derive(Clone)]
+pub struct SamplePlayer<T> {
+    buffer: SampleBuffer,
+    root_frequency: f64,
+    rate: f64,
+    position: f64,
+    forward: bool,
+    finished: bool,
+    input: T,
+}
+
+impl<T> SamplePlayer<T> {
+    pub fn new(buffer: SampleBuffer, input: T) -> WaveGenerator<Self> {
+        Self {
+            root_frequency: midi_note_number_to_frequency(buffer.root_key),
+            // Resample from the rate of the file to the rate of the graph.
+            rate: buffer.sample_rate as f64 / 44100.0,
+            position: buffer.start as f64,
+            forward: true,
+            finished: buffer.frames() == 0,
+            buffer,
+            input,
+        }
+        .into()
+    }
+
+    /// Frame that follows `index`, for interpolation. Wraps around at the end of a forward loop.
+    #[inline]
+    fn next_index(&self, index: usize) -> usize {
+        let (start, end) = self.buffer.loop_range();
+        match self.buffer.loop_mode {
+            LoopMode::Forward if index + 1 == end => start,
+            _ => (index + 1).min(self.buffer.frames() - 1),
+        }
+    }
+
+    #[inline]
+    fn read(&self, channel: usize) -> f64 {
+        let index = self.position as usize;
+        let fraction = self.position - index as f64;
+        let a = self.buffer.frame(index, channel);
+        let b = self.buffer.frame(self.next_index(index), channel);
+        a + (b - a) * fraction
+    }
+
+    fn advance(&mut self, step: f64) {
+        let (start, end) = self.buffer.loop_range();
+        let (start, end) = (start as f64, end as f64);
+
+        match self.buffer.loop_mode {
+            LoopMode::OneShot => {
+                self.position += step;
+                if self.position >= (self.buffer.frames() - 1) as f64 {
+                    self.finished = true;
+                }
+            }
+            LoopMode::Forward => {
+                self.position += step;
+                if self.position >= end && end > start {
+                    self.position = start + (self.position - end) % (end - start);
+                }
+            }
+            LoopMode::PingPong => {
+                if self.forward {
+                    self.position += step;
+                    if self.position >= end - 1.0 {
+                        self.position = (2.0 * (end - 1.0) - self.position).max(start);
+                        self.forward = false;
+                    }
+                } else {
+                    self.position -= step;
+                    if self.position <= start {
+                        self.position = (2.0 * start - self.position).min(end - 1.0);
+                        self.forward = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<W: Wave> Wave for SamplePlayer<W> {
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        let frequency = self.input.next_sample();
+        if self.finished {
+            return (0.0, 0.0);
+        }
+
+        let frame = (self.read(0), self.read(self.buffer.channels - 1));
+        self.advance(frequency / self.root_frequency * self.rate);
+        frame
+    }
+}
+
+make_partial!(PartialSamplePlayer { buffer: SampleBuffer } => SamplePlayer);
+
+pub fn sample_player(buffer: SampleBuffer) -> PartialWaveBuilder<PartialSamplePlayer> {
+    PartialSamplePlayer::new(buffer)
+}
@@ -0,0 +1,164 @@
+use std::{error::Error, fmt, io};
+
+use crate::{
+    riff::{self, u16_at, u32_at},
+    sampler::{LoopMode, SampleBuffer},
+};
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+    /// The file is not a RIFF/WAVE file or one of its chunks is broken.
+    Malformed(&'static str),
+    Unsupported {
+        format: u16,
+        bits: u16,
+    },
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavError::Io(e) => write!(f, "could not read wav file: {}", e),
+            WavError::Malformed(reason) => write!(f, "malformed wav file: {}", reason),
+            WavError::Unsupported { format, bits } => {
+                write!(f, "unsupported wav format {} with {} bits", format, bits)
+            }
+        }
+    }
+}
+
+impl Error for WavError {}
+
+impl From<io::Error> for WavError {
+    fn from(e: io::Error) -> Self {
+        WavError::Io(e)
+    }
+}
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+struct Format {
+    format: u16,
+    channels: usize,
+    sample_rate: u32,
+    bits: u16,
+}
+
+fn parse_format(chunk: &[u8]) -> Result<Format, WavError> {
+    let too_short = || WavError::Malformed("fmt chunk too short");
+    let mut format = u16_at(chunk, 0).ok_or_else(too_short)?;
+    let channels = u16_at(chunk, 2).ok_or_else(too_short)?;
+    let sample_rate = u32_at(chunk, 4).ok_or_else(too_short)?;
+    let bits = u16_at(chunk, 14).ok_or_else(too_short)?;
+
+    if format == FORMAT_EXTENSIBLE {
+        // The actual format is stored in the first two bytes of the sub format GUID.
+        format = u16_at(chunk, 24).ok_or_else(too_short)?;
+    }
+
+    if channels == 0 {
+        return Err(WavError::Malformed("no channels"));
+    }
+
+    Ok(Format {
+        format,
+        channels: channels as usize,
+        sample_rate,
+        bits,
+    })
+}
+
+fn decode(format: &Format, data: &[u8]) -> Result<Vec<f64>, WavError> {
+    let unsupported = WavError::Unsupported {
+        format: format.format,
+        bits: format.bits,
+    };
+
+    let samples = match (format.format, format.bits) {
+        (FORMAT_PCM, 8) => data.iter().map(|b| (*b as f64 - 128.0) / 128.0).collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f64 / 32768.0)
+            .collect(),
+        (FORMAT_PCM, 24) => data
+            .chunks_exact(3)
+            // Shift into the upper bytes of an i32 to get the sign right.
+            .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f64 / 2147483648.0)
+            .collect(),
+        (FORMAT_PCM, 32) => data
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2147483648.0)
+            .collect(),
+        (FORMAT_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect(),
+        (FORMAT_FLOAT, 64) => data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        _ => return Err(unsupported),
+    };
+
+    Ok(samples)
+}
+
+/// Reads the root key and the first loop from a `smpl` chunk, if there is one.
+fn apply_sampler_chunk(buffer: &mut SampleBuffer, chunk: &[u8]) {
+    if let Some(root_key) = u32_at(chunk, 12) {
+        buffer.root_key = root_key.min(127) as f64;
+    }
+
+    let loops = u32_at(chunk, 28).unwrap_or(0);
+    if loops == 0 {
+        return;
+    }
+
+    if let (Some(kind), Some(start), Some(end)) =
+        (u32_at(chunk, 40), u32_at(chunk, 44), u32_at(chunk, 48))
+    {
+        buffer.loop_mode = match kind {
+            1 => LoopMode::PingPong,
+            _ => LoopMode::Forward,
+        };
+        buffer.loop_start = start as usize;
+        // The loop end in the file is inclusive.
+        buffer.loop_end = end as usize + 1;
+    }
+}
+
+pub(crate) fn parse_wav(bytes: &[u8]) -> Result<SampleBuffer, WavError> {
+    let chunks = match riff::parse(bytes) {
+        Some((b"WAVE", chunks)) => chunks,
+        _ => return Err(WavError::Malformed("missing RIFF/WAVE header")),
+    };
+
+    let mut format = None;
+    let mut samples = None;
+    let mut sampler_chunk = None;
+
+    for chunk in chunks {
+        match chunk.id {
+            b"fmt " => format = Some(parse_format(chunk.data)?),
+            b"data" => samples = Some(chunk.data),
+            b"smpl" => sampler_chunk = Some(chunk.data),
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or(WavError::Malformed("missing fmt chunk"))?;
+    let samples = decode(
+        &format,
+        samples.ok_or(WavError::Malformed("missing data chunk"))?,
+    )?;
+
+    let mut buffer = SampleBuffer::new(samples, format.channels, format.sample_rate);
+    if let Some(chunk) = sampler_chunk {
+        apply_sampler_chunk(&mut buffer, chunk);
+    }
+
+    Ok(buffer)
+}
@@ -0,0 +1,464 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    instrument::{Patch, PolyInstrument, PolyInstrumentWave, SharedPatch},
+    midi::Recorder,
+    random::Random,
+    wave::{Wave, WaveGenerator},
+    waves::ADSREvent,
+};
+
+/// A note of a [`Pattern`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub key: u8,
+    pub velocity: u8,
+    /// How long the note is held, in steps.
+    pub length: f64,
+    /// Chance the note plays each time the pattern comes by, between 0.0 and 1.0.
+    pub probability: f64,
+}
+
+impl Step {
+    /// A note held for one step, which always plays.
+    pub fn new(key: u8, velocity: u8) -> Self {
+        Self {
+            key,
+            velocity,
+            length: 1.0,
+            probability: 1.0,
+        }
+    }
+
+    pub fn with_length(mut self, steps: f64) -> Self {
+        self.length = steps;
+        self
+    }
+
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// A row of steps of equal length. Steps can hold several notes, or none for a rest.
+///
+/// ```
+/// use rust_audio_shenanigans::sequencer::{Pattern, Step};
+///
+/// // Four on the floor in sixteenth notes, with a ghost note that plays half of the time.
+/// let kick = Pattern::new(16, 0.25)
+///     .with_step(0, Step::new(36, 120))
+///     .with_step(4, Step::new(36, 120))
+///     .with_step(8, Step::new(36, 120))
+///     .with_step(11, Step::new(36, 60).with_probability(0.5))
+///     .with_step(12, Step::new(36, 120));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    steps: Vec<Vec<Step>>,
+    /// Length of a step in beats.
+    step_length: f64,
+}
+
+impl Pattern {
+    /// `steps` empty steps, each `step_length` beats long.
+    pub fn new(steps: usize, step_length: f64) -> Self {
+        Self {
+            steps: vec![Vec::new(); steps],
+            step_length,
+        }
+    }
+
+    /// Add a note to the step at `index`.
+    pub fn with_step(mut self, index: usize, step: Step) -> Self {
+        self.add_step(index, step);
+        self
+    }
+
+    pub fn add_step(&mut self, index: usize, step: Step) {
+        if let Some(notes) = self.steps.get_mut(index) {
+            notes.push(step);
+        }
+    }
+
+    /// Remove all notes of the step at `index`.
+    pub fn clear_step(&mut self, index: usize) {
+        if let Some(notes) = self.steps.get_mut(index) {
+            notes.clear();
+        }
+    }
+
+    /// Length of the pattern in beats.
+    pub fn length(&self) -> f64 {
+        self.steps.len() as f64 * self.step_length
+    }
+
+    /// The notes of the pattern with the beat they start at.
+    fn notes(&self) -> impl Iterator<Item = (f64, &Step)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, notes)| {
+                notes
+                    .iter()
+                    .map(move |note| (index as f64 * self.step_length, note))
+            })
+    }
+}
+
+/// A pattern played over and over for the length of the clip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pattern: Pattern,
+    /// Length in beats.
+    length: f64,
+}
+
+impl Clip {
+    /// Play `pattern` once.
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            length: pattern.length(),
+            pattern,
+        }
+    }
+
+    /// Loop the pattern for `beats`, cutting the last repetition short if needed.
+    pub fn with_length(mut self, beats: f64) -> Self {
+        self.length = beats;
+        self
+    }
+
+    /// Loop the pattern `times` times.
+    pub fn with_repeats(self, times: usize) -> Self {
+        let length = self.pattern.length() * times as f64;
+        self.with_length(length)
+    }
+}
+
+/// An instrument together with the clips it plays, placed at bars of the song.
+#[derive(Clone)]
+pub struct Track {
+    patch: SharedPatch,
+    /// Clips with the bar they start at.
+    clips: Vec<(usize, Clip)>,
+}
+
+impl Track {
+    pub fn new<P>(patch: P) -> Self
+    where
+        P: Patch + Send + Sync + 'static,
+        P::Voice: 'static,
+    {
+        Self {
+            patch: SharedPatch::new(patch),
+            clips: Vec::new(),
+        }
+    }
+
+    /// Play `clip` from the start of `bar` (counting from 0).
+    pub fn with_clip(mut self, bar: usize, clip: Clip) -> Self {
+        self.add_clip(bar, clip);
+        self
+    }
+
+    pub fn add_clip(&mut self, bar: usize, clip: Clip) {
+        self.clips.push((bar, clip));
+    }
+}
+
+/// Tracks arranged in bars, at a fixed tempo.
+///
+/// ```
+/// use rust_audio_shenanigans::drums::DrumKit;
+/// use rust_audio_shenanigans::sequencer::{Clip, Pattern, Song, Step, Track};
+/// use rust_audio_shenanigans::waves::triangle;
+///
+/// let beat = Pattern::new(4, 1.0)
+///     .with_step(0, Step::new(36, 120))
+///     .with_step(2, Step::new(38, 100));
+/// let bass = Pattern::new(8, 0.5)
+///     .with_step(0, Step::new(36, 100).with_length(3.0))
+///     .with_step(4, Step::new(43, 100).with_length(3.0));
+///
+/// let drums = Track::new(DrumKit::general_midi()).with_clip(0, Clip::new(beat).with_repeats(4));
+/// let song = Song::new(120.0)
+///     .with_track(drums)
+///     .with_track(Track::new(triangle()).with_clip(2, Clip::new(bass).with_repeats(2)));
+/// let audio = song.player().source.render(1.0);
+/// ```
+#[derive(Clone)]
+pub struct Song {
+    bpm: f64,
+    beats_per_bar: usize,
+    tracks: Vec<Track>,
+    seed: Option<u64>,
+}
+
+impl Song {
+    /// A song in 4/4 at `bpm` beats per minute.
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            bpm,
+            beats_per_bar: 4,
+            tracks: Vec::new(),
+            seed: None,
+        }
+    }
+
+    pub fn with_beats_per_bar(mut self, beats: usize) -> Self {
+        self.beats_per_bar = beats.max(1);
+        self
+    }
+
+    pub fn with_track(mut self, track: Track) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    /// Decide which notes with a probability play with a generator seeded by `seed`, so the same
+    /// song always renders the same.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    fn samples_per_beat(&self) -> f64 {
+        60.0 / self.bpm * 44100.0
+    }
+
+    /// Length of the song in seconds, up to the end of its last clip.
+    pub fn length(&self) -> f64 {
+        let beats = self
+            .tracks
+            .iter()
+            .flat_map(|track| &track.clips)
+            .map(|(bar, clip)| (bar * self.beats_per_bar) as f64 + clip.length)
+            .fold(0.0, f64::max);
+        beats * 60.0 / self.bpm
+    }
+
+    /// Play the song from the start. Notes with a probability are decided now, every player
+    /// plays a different take.
+    pub fn player(&self) -> WaveGenerator<SongPlayer> {
+        let mut random = match self.seed {
+            Some(seed) => Random::with_seed(seed),
+            None => Random::new(),
+        };
+        let samples_per_beat = self.samples_per_beat();
+        let to_sample = |beat: f64| (beat * samples_per_beat).round() as usize;
+
+        let mut notes = Vec::new();
+        let mut tracks = Vec::new();
+        for (index, track) in self.tracks.iter().enumerate() {
+            for (bar, clip) in &track.clips {
+                let start = (bar * self.beats_per_bar) as f64;
+                let pattern_length = clip.pattern.length();
+                let repeats = match pattern_length > 0.0 {
+                    true => (clip.length / pattern_length).ceil() as usize,
+                    false => 0,
+                };
+
+                for repeat in 0..repeats {
+                    let offset = start + repeat as f64 * pattern_length;
+                    for (beat, step) in clip.pattern.notes() {
+                        let beat = offset + beat;
+                        if beat >= start + clip.length || random.next_f64() >= step.probability {
+                            continue;
+                        }
+                        let end = beat + step.length * clip.pattern.step_length;
+                        notes.push(SongNote {
+                            start: to_sample(beat),
+                            end: to_sample(end),
+                            track: index,
+                            key: step.key as usize,
+                            velocity: step.velocity,
+                        });
+                    }
+                }
+            }
+
+            let (instrument, wave) = PolyInstrument::new(track.patch.clone());
+            tracks.push((instrument, wave.source));
+        }
+
+        // A note that lasts into the next note on its key ends where that one starts, so its release
+        // doesn't cut the next note short.
+        notes.sort_by_key(|note| (note.track, note.key, note.start));
+        for i in 1..notes.len() {
+            let (previous, note) = (&notes[i - 1], &notes[i]);
+            if (previous.track, previous.key) == (note.track, note.key) {
+                notes[i - 1].end = previous.end.min(note.start);
+            }
+        }
+
+        let mut events: Vec<_> = notes
+            .into_iter()
+            .flat_map(|note| {
+                let event = |sample, event| SongEvent {
+                    sample,
+                    track: note.track,
+                    key: note.key,
+                    event,
+                };
+                [
+                    event(note.start, ADSREvent::Press(note.velocity)),
+                    // Every note sounds for at least a sample.
+                    event(note.end.max(note.start + 1), ADSREvent::Release),
+                ]
+            })
+            .collect();
+
+        // Notes ending on a sample are released before the notes starting on it are pressed.
+        events.sort_by_key(|event| (event.sample, matches!(event.event, ADSREvent::Press(_))));
+
+        SongPlayer {
+            state: Arc::new(Mutex::new(SongState {
+                tracks,
+                events,
+                next: 0,
+                position: 0,
+                length: (self.length() * 44100.0).round() as usize,
+                seconds_per_beat: 60.0 / self.bpm,
+                recorder: None,
+            })),
+        }
+        .into()
+    }
+}
+
+/// A note of a song, in samples from the start.
+struct SongNote {
+    start: usize,
+    end: usize,
+    track: usize,
+    key: usize,
+    velocity: u8,
+}
+
+struct SongEvent {
+    sample: usize,
+    track: usize,
+    key: usize,
+    event: ADSREvent,
+}
+
+struct SongState {
+    tracks: Vec<(PolyInstrument<SharedPatch>, PolyInstrumentWave<SharedPatch>)>,
+    /// Note events in the order they are played.
+    events: Vec<SongEvent>,
+    /// Index of the next event to play.
+    next: usize,
+    /// Samples played so far.
+    position: usize,
+    length: usize,
+    seconds_per_beat: f64,
+    recorder: Option<Recorder>,
+}
+
+impl SongState {
+    fn next_frame(&mut self) -> (f64, f64) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.sample > self.position {
+                break;
+            }
+            self.tracks[event.track].0.play(event.key, event.event);
+            if let Some(recorder) = &self.recorder {
+                recorder.set_position(self.position as f64 / 44100.0);
+                recorder.record_note((event.track % 16) as u8, event.key, event.event);
+            }
+            self.next += 1;
+        }
+        self.position += 1;
+
+        self.tracks
+            .iter_mut()
+            .fold((0.0, 0.0), |(l, r), (_, wave)| {
+                let (wl, wr) = wave.next_frame();
+                (l + wl, r + wr)
+            })
+    }
+}
+
+/// Plays a [`Song`], starting every note on the exact sample it falls on. Cloning shares the
+/// playback.
+#[derive(Clone)]
+pub struct SongPlayer {
+    state: Arc<Mutex<SongState>>,
+}
+
+impl SongPlayer {
+    /// Seconds played so far.
+    pub fn position(&self) -> f64 {
+        self.state.lock().unwrap().position as f64 / 44100.0
+    }
+
+    /// Record the notes played from now on with `recorder`, each track on the MIDI channel of
+    /// its index (wrapping around after 16 tracks).
+    pub fn set_recorder(&self, recorder: Option<Recorder>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(recorder) = &recorder {
+            recorder.set_position(state.position as f64 / 44100.0);
+            recorder.record_tempo(state.seconds_per_beat);
+        }
+        state.recorder = recorder;
+    }
+
+    /// Whether the song has played to its end. Released notes may still be fading out.
+    pub fn is_finished(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.position >= state.length && state.next >= state.events.len()
+    }
+
+    /// Render the rest of the song, and `tail` seconds after its end for released notes to fade
+    /// out, as stereo frames.
+    pub fn render(&mut self, tail: f64) -> Vec<(f64, f64)> {
+        let mut state = self.state.lock().unwrap();
+        let end = state.length + (tail * 44100.0) as usize;
+        let remaining = end.saturating_sub(state.position);
+        (0..remaining).map(|_| state.next_frame()).collect()
+    }
+}
+
+impl Wave for SongPlayer {
+    fn next_sample(&mut self) -> f64 {
+        let (l, r) = self.next_frame();
+        (l + r) / 2.0
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.state.lock().unwrap().next_frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waves::triangle;
+
+    /// Loudest sample between `from` and `to`, in samples.
+    fn peak(audio: &[(f64, f64)], from: usize, to: usize) -> f64 {
+        audio[from..to]
+            .iter()
+            .map(|(l, r)| l.abs().max(r.abs()))
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn overlapping_note_on_the_same_key_keeps_its_length() {
+        // At 120 bpm a beat is 22050 samples. The first note lasts into the second one, which
+        // starts on beat 2 and should sound until beat 4.
+        let pattern = Pattern::new(4, 1.0)
+            .with_step(0, Step::new(60, 100).with_length(3.0))
+            .with_step(2, Step::new(60, 100).with_length(2.0));
+        let song =
+            Song::new(120.0).with_track(Track::new(triangle()).with_clip(0, Clip::new(pattern)));
+        let audio = song.player().source.render(0.5);
+
+        // Past the end of the first note, the second one still sounds.
+        assert!(peak(&audio, 70_000, 88_000) > 0.1);
+        // After its own end and release, it is gone.
+        assert!(peak(&audio, 88_200 + 8_820, audio.len()) < 0.01);
+    }
+}
@@ -0,0 +1,568 @@
+use std::{error::Error, fmt, io, ops::RangeInclusive, path::Path};
+
+use crate::{
+    instrument::SharedPatch,
+    midi::{PatchBank, PERCUSSION_BANK},
+    riff::{self, i16_at, u16_at, u32_at, Chunk},
+    sampler::{LoopMode, SampleBuffer, Sampler, Zone},
+};
+
+#[derive(Debug)]
+pub enum SoundFontError {
+    Io(io::Error),
+    /// The file is not a RIFF/sfbk file or one of its chunks is broken.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for SoundFontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoundFontError::Io(e) => write!(f, "could not read soundfont: {}", e),
+            SoundFontError::Malformed(reason) => write!(f, "malformed soundfont: {}", reason),
+        }
+    }
+}
+
+impl Error for SoundFontError {}
+
+impl From<io::Error> for SoundFontError {
+    fn from(e: io::Error) -> Self {
+        SoundFontError::Io(e)
+    }
+}
+
+/// Generator operators this crate understands, as numbered by the SF2 specification.
+mod operator {
+    pub const START_OFFSET: u16 = 0;
+    pub const START_LOOP_OFFSET: u16 = 2;
+    pub const END_LOOP_OFFSET: u16 = 3;
+    pub const START_COARSE_OFFSET: u16 = 4;
+    pub const PAN: u16 = 17;
+    pub const ATTACK: u16 = 34;
+    pub const DECAY: u16 = 36;
+    pub const SUSTAIN: u16 = 37;
+    pub const RELEASE: u16 = 38;
+    pub const INSTRUMENT: u16 = 41;
+    pub const KEY_RANGE: u16 = 43;
+    pub const VELOCITY_RANGE: u16 = 44;
+    pub const START_LOOP_COARSE_OFFSET: u16 = 45;
+    pub const ATTENUATION: u16 = 48;
+    pub const END_LOOP_COARSE_OFFSET: u16 = 50;
+    pub const COARSE_TUNE: u16 = 51;
+    pub const FINE_TUNE: u16 = 52;
+    pub const SAMPLE_ID: u16 = 53;
+    pub const SAMPLE_MODES: u16 = 54;
+    pub const ROOT_KEY: u16 = 58;
+}
+
+/// Envelope times default to -12000 timecents, about one millisecond.
+const DEFAULT_TIMECENTS: i16 = -12000;
+
+/// Sample types with this bit set live in ROM of a sound card and are not part of the file.
+const SAMPLE_TYPE_ROM: u16 = 0x8000;
+
+/// Sets one parameter of a zone. Amounts are signed, except for ranges, which keep the lowest
+/// value in the low byte and the highest in the high byte.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    pub operator: u16,
+    pub amount: i16,
+}
+
+impl Generator {
+    pub fn range(&self) -> RangeInclusive<u8> {
+        let [low, high] = self.amount.to_le_bytes();
+        low..=high
+    }
+}
+
+/// Connects a controller to a generator. Modulators are read, but not applied to the voices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modulator {
+    pub source: u16,
+    pub destination: u16,
+    pub amount: i16,
+    pub amount_source: u16,
+    pub transform: u16,
+}
+
+/// The generators and modulators of a preset or instrument zone.
+#[derive(Debug, Clone, Default)]
+pub struct SoundFontZone {
+    pub generators: Vec<Generator>,
+    pub modulators: Vec<Modulator>,
+}
+
+impl SoundFontZone {
+    /// The amount of the last generator with the given operator.
+    pub fn generator(&self, operator: u16) -> Option<i16> {
+        self.generators
+            .iter()
+            .rev()
+            .find(|generator| generator.operator == operator)
+            .map(|generator| generator.amount)
+    }
+
+    fn range(&self, operator: u16) -> RangeInclusive<u8> {
+        self.generators
+            .iter()
+            .rev()
+            .find(|generator| generator.operator == operator)
+            .map_or(0..=127, Generator::range)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub program: u16,
+    /// Bank 128 holds the percussion kits.
+    pub bank: u16,
+    pub zones: Vec<SoundFontZone>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub name: String,
+    pub zones: Vec<SoundFontZone>,
+}
+
+/// Describes where a sample lives in the sample data of the file. Positions are in frames from the
+/// start of the sample data.
+#[derive(Debug, Clone)]
+pub struct SampleHeader {
+    pub name: String,
+    pub start: u32,
+    pub end: u32,
+    pub loop_start: u32,
+    pub loop_end: u32,
+    pub sample_rate: u32,
+    pub original_pitch: u8,
+    /// Correction of the pitch in cents.
+    pub pitch_correction: i8,
+    pub link: u16,
+    pub kind: u16,
+}
+
+/// A parsed SF2 file. Each preset can be turned into a [`Sampler`] and played by a
+/// `PolyInstrument`.
+///
+/// ```no_run
+/// use rust_audio_shenanigans::instrument::PolyInstrument;
+/// use rust_audio_shenanigans::soundfont::SoundFont;
+///
+/// let font = SoundFont::from_file("GeneralUser.sf2").unwrap();
+/// // Bank 0, program 0: the acoustic grand piano of a General MIDI soundfont.
+/// let piano = font.sampler(0, 0).unwrap();
+/// let (mut inst, wave) = PolyInstrument::new(piano);
+/// ```
+pub struct SoundFont {
+    presets: Vec<Preset>,
+    instruments: Vec<Instrument>,
+    samples: Vec<SampleHeader>,
+    /// One buffer per sample header, shared by all zones that play it.
+    buffers: Vec<SampleBuffer>,
+}
+
+impl SoundFont {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SoundFontError> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, SoundFontError> {
+        let chunks = match riff::parse(bytes) {
+            Some((b"sfbk", chunks)) => chunks,
+            _ => return Err(SoundFontError::Malformed("missing RIFF/sfbk header")),
+        };
+
+        let mut sample_data = None;
+        let mut hydra = None;
+
+        for chunk in chunks.iter().filter(|chunk| chunk.id == b"LIST") {
+            match chunk.list() {
+                Some((b"sdta", sub_chunks)) => sample_data = Some(sub_chunks),
+                Some((b"pdta", sub_chunks)) => hydra = Some(sub_chunks),
+                _ => {}
+            }
+        }
+
+        let samples = decode_samples(&sample_data.unwrap_or_default());
+        let hydra = hydra.ok_or(SoundFontError::Malformed("missing pdta list"))?;
+        let find = |id: &[u8]| {
+            hydra
+                .iter()
+                .find(|chunk| chunk.id == id)
+                .map(|chunk| chunk.data)
+                .ok_or(SoundFontError::Malformed("missing pdta sub chunk"))
+        };
+
+        let preset_zones = parse_zones(find(b"pbag")?, find(b"pmod")?, find(b"pgen")?)?;
+        let instrument_zones = parse_zones(find(b"ibag")?, find(b"imod")?, find(b"igen")?)?;
+
+        let presets = parse_headers(find(b"phdr")?, 38, 24, &preset_zones)?
+            .into_iter()
+            .map(|(record, name, zones)| Preset {
+                name,
+                program: u16_at(record, 20).unwrap_or(0),
+                bank: u16_at(record, 22).unwrap_or(0),
+                zones,
+            })
+            .collect();
+        let instruments = parse_headers(find(b"inst")?, 22, 20, &instrument_zones)?
+            .into_iter()
+            .map(|(_, name, zones)| Instrument { name, zones })
+            .collect();
+        let headers = parse_sample_headers(find(b"shdr")?)?;
+
+        let buffers = headers
+            .iter()
+            .map(|header| sample_buffer(header, &samples))
+            .collect();
+
+        Ok(Self {
+            presets,
+            instruments,
+            samples: headers,
+            buffers,
+        })
+    }
+
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    pub fn instruments(&self) -> &[Instrument] {
+        &self.instruments
+    }
+
+    pub fn samples(&self) -> &[SampleHeader] {
+        &self.samples
+    }
+
+    pub fn preset(&self, bank: u16, program: u16) -> Option<&Preset> {
+        self.presets
+            .iter()
+            .find(|preset| preset.bank == bank && preset.program == program)
+    }
+
+    /// Build a sampler that plays the preset with the given bank and program.
+    ///
+    /// Key and velocity ranges, tuning, root key, attenuation, pan, loops, sample offsets and the
+    /// attack, decay, sustain and release of the volume envelope are applied. Everything else,
+    /// including the modulators, is ignored.
+    pub fn sampler(&self, bank: u16, program: u16) -> Option<Sampler> {
+        let preset = self.preset(bank, program)?;
+        let (preset_global, preset_zones) = split_global(&preset.zones, operator::INSTRUMENT);
+
+        let mut sampler = Sampler::default();
+        for preset_zone in preset_zones {
+            let instrument = preset_zone
+                .generator(operator::INSTRUMENT)
+                .and_then(|index| self.instruments.get(index as u16 as usize));
+            let Some(instrument) = instrument else {
+                continue;
+            };
+
+            let (instrument_global, instrument_zones) =
+                split_global(&instrument.zones, operator::SAMPLE_ID);
+            for instrument_zone in instrument_zones {
+                let generators = Generators {
+                    instrument: [instrument_global, instrument_zone],
+                    preset: [preset_global, preset_zone],
+                };
+                if let Some(zone) = self.zone(&generators) {
+                    sampler.add_zone(zone);
+                }
+            }
+        }
+
+        Some(sampler)
+    }
+
+    fn zone(&self, generators: &Generators) -> Option<Zone> {
+        let index = generators.instrument[1].generator(operator::SAMPLE_ID)? as u16 as usize;
+        let header = self.samples.get(index)?;
+        let mut buffer = self.buffers.get(index)?.clone();
+
+        let keys = generators.range(operator::KEY_RANGE)?;
+        let velocities = generators.range(operator::VELOCITY_RANGE)?;
+
+        let root_key = match generators.instrument(operator::ROOT_KEY) {
+            Some(key @ 0..=127) => key as u8,
+            _ => header.original_pitch,
+        };
+        buffer.root_key = root_key as f64 - header.pitch_correction as f64 / 100.0;
+
+        let offset = |fine, coarse| {
+            generators.instrument(fine).unwrap_or(0) as i64
+                + generators.instrument(coarse).unwrap_or(0) as i64 * 32768
+        };
+        let shift = |position: usize, offset: i64| {
+            (position as i64 + offset).clamp(0, buffer.frames() as i64) as usize
+        };
+        let start = shift(
+            0,
+            offset(operator::START_OFFSET, operator::START_COARSE_OFFSET),
+        );
+        let loop_start = shift(
+            buffer.loop_start,
+            offset(
+                operator::START_LOOP_OFFSET,
+                operator::START_LOOP_COARSE_OFFSET,
+            ),
+        );
+        let loop_end = shift(
+            buffer.loop_end,
+            offset(operator::END_LOOP_OFFSET, operator::END_LOOP_COARSE_OFFSET),
+        );
+        // Mode 1 loops all the time, mode 3 until the key is released. Both simply loop here.
+        let loop_mode = match generators.instrument(operator::SAMPLE_MODES).unwrap_or(0) & 3 {
+            1 | 3 => LoopMode::Forward,
+            _ => LoopMode::OneShot,
+        };
+        let buffer = buffer
+            .with_start(start)
+            .with_loop(loop_mode, loop_start, loop_end);
+
+        let tune = generators.sum(operator::COARSE_TUNE, 0) as f64 * 100.0
+            + generators.sum(operator::FINE_TUNE, 0) as f64;
+        let time = |operator| timecents_to_seconds(generators.sum(operator, DEFAULT_TIMECENTS));
+        let sustain = centibels_to_gain(generators.sum(operator::SUSTAIN, 0).clamp(0, 1440));
+
+        Some(
+            Zone::new(buffer)
+                .with_keys(keys)
+                .with_velocities(velocities)
+                .with_fine_tune(tune)
+                .with_volume(-generators.sum(operator::ATTENUATION, 0).max(0) as f64 / 10.0)
+                .with_pan(generators.sum(operator::PAN, 0).clamp(-500, 500) as f64 / 500.0)
+                .with_envelope(
+                    time(operator::ATTACK),
+                    time(operator::DECAY),
+                    sustain,
+                    time(operator::RELEASE),
+                ),
+        )
+    }
+}
+
+/// Programs missing from a bank fall back to the same program in bank 0, or to the first program
+/// of the percussion bank, like General MIDI players do with variation banks.
+impl PatchBank for SoundFont {
+    fn patch(&self, _channel: u8, bank: u16, program: u8) -> Option<SharedPatch> {
+        let program = program as u16;
+        let sampler = self.sampler(bank, program).or_else(|| match bank {
+            PERCUSSION_BANK => self.sampler(bank, 0),
+            _ => self.sampler(0, program),
+        })?;
+        Some(SharedPatch::new(sampler))
+    }
+}
+
+/// The generators that apply to one instrument zone. Global zones provide defaults for the zones
+/// of their level. Instrument values are absolute, preset values are added on top of them.
+struct Generators<'a> {
+    instrument: [&'a SoundFontZone; 2],
+    preset: [&'a SoundFontZone; 2],
+}
+
+impl Generators<'_> {
+    fn instrument(&self, operator: u16) -> Option<i16> {
+        level(&self.instrument, operator)
+    }
+
+    fn sum(&self, operator: u16, default: i16) -> i32 {
+        self.instrument(operator).unwrap_or(default) as i32
+            + level(&self.preset, operator).unwrap_or(0) as i32
+    }
+
+    /// The intersection of the ranges of both levels, if there is one.
+    fn range(&self, operator: u16) -> Option<RangeInclusive<u8>> {
+        let ranges = [&self.instrument, &self.preset].map(|[global, local]| {
+            if local.generator(operator).is_some() {
+                local.range(operator)
+            } else {
+                global.range(operator)
+            }
+        });
+
+        let low = *ranges[0].start().max(ranges[1].start());
+        let high = *ranges[0].end().min(ranges[1].end());
+        (low <= high).then_some(low..=high)
+    }
+}
+
+fn level(zones: &[&SoundFontZone; 2], operator: u16) -> Option<i16> {
+    zones[1]
+        .generator(operator)
+        .or_else(|| zones[0].generator(operator))
+}
+
+static EMPTY_ZONE: SoundFontZone = SoundFontZone {
+    generators: Vec::new(),
+    modulators: Vec::new(),
+};
+
+/// Split off the global zone, which is the first zone if it lacks the generator that links it to
+/// an instrument or a sample.
+fn split_global(zones: &[SoundFontZone], link: u16) -> (&SoundFontZone, &[SoundFontZone]) {
+    match zones.split_first() {
+        Some((first, rest)) if first.generator(link).is_none() => (first, rest),
+        _ => (&EMPTY_ZONE, zones),
+    }
+}
+
+fn timecents_to_seconds(timecents: i32) -> f64 {
+    2.0f64.powf(timecents as f64 / 1200.0)
+}
+
+fn centibels_to_gain(centibels: i32) -> f64 {
+    10.0f64.powf(-centibels as f64 / 200.0)
+}
+
+/// Read the 16 bit sample data, refined to 24 bits if the file has an `sm24` chunk.
+fn decode_samples(chunks: &[Chunk]) -> Vec<f64> {
+    let find = |id: &[u8]| chunks.iter().find(|chunk| chunk.id == id);
+    let Some(high) = find(b"smpl") else {
+        return Vec::new();
+    };
+
+    match find(b"sm24").filter(|low| low.data.len() >= high.data.len() / 2) {
+        Some(low) => high
+            .data
+            .chunks_exact(2)
+            .zip(low.data)
+            .map(|(b, low)| i32::from_le_bytes([0, *low, b[0], b[1]]) as f64 / 2147483648.0)
+            .collect(),
+        None => high
+            .data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f64 / 32768.0)
+            .collect(),
+    }
+}
+
+/// Read the bags of one level and the generators and modulators they point to.
+fn parse_zones(
+    bags: &[u8],
+    modulators: &[u8],
+    generators: &[u8],
+) -> Result<Vec<SoundFontZone>, SoundFontError> {
+    let modulators: Vec<_> = modulators
+        .chunks_exact(10)
+        .map(|m| Modulator {
+            source: u16_at(m, 0).unwrap(),
+            destination: u16_at(m, 2).unwrap(),
+            amount: i16_at(m, 4).unwrap(),
+            amount_source: u16_at(m, 6).unwrap(),
+            transform: u16_at(m, 8).unwrap(),
+        })
+        .collect();
+    let generators: Vec<_> = generators
+        .chunks_exact(4)
+        .map(|g| Generator {
+            operator: u16_at(g, 0).unwrap(),
+            amount: i16_at(g, 2).unwrap(),
+        })
+        .collect();
+
+    // Each bag spans up to the start of the next one, the last bag only marks the end.
+    let bags: Vec<_> = bags
+        .chunks_exact(4)
+        .map(|b| {
+            (
+                u16_at(b, 0).unwrap() as usize,
+                u16_at(b, 2).unwrap() as usize,
+            )
+        })
+        .collect();
+
+    bags.windows(2)
+        .map(|pair| {
+            let ((generator, modulator), (next_generator, next_modulator)) = (pair[0], pair[1]);
+            Ok(SoundFontZone {
+                generators: generators
+                    .get(generator..next_generator)
+                    .ok_or(SoundFontError::Malformed("generator index out of range"))?
+                    .to_vec(),
+                modulators: modulators
+                    .get(modulator..next_modulator)
+                    .ok_or(SoundFontError::Malformed("modulator index out of range"))?
+                    .to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Read preset or instrument headers of `size` bytes, each with a name and the index of its first
+/// bag at `bag_offset`. The terminal header only marks the end of the last bag.
+#[allow(clippy::type_complexity)]
+fn parse_headers<'a>(
+    data: &'a [u8],
+    size: usize,
+    bag_offset: usize,
+    zones: &[SoundFontZone],
+) -> Result<Vec<(&'a [u8], String, Vec<SoundFontZone>)>, SoundFontError> {
+    let records: Vec<_> = data.chunks_exact(size).collect();
+
+    records
+        .windows(2)
+        .map(|pair| {
+            let start = u16_at(pair[0], bag_offset).unwrap() as usize;
+            let end = u16_at(pair[1], bag_offset).unwrap() as usize;
+            let zones = zones
+                .get(start..end)
+                .ok_or(SoundFontError::Malformed("bag index out of range"))?;
+            Ok((pair[0], name(pair[0]), zones.to_vec()))
+        })
+        .collect()
+}
+
+fn parse_sample_headers(data: &[u8]) -> Result<Vec<SampleHeader>, SoundFontError> {
+    let records: Vec<_> = data.chunks_exact(46).collect();
+    let Some((_terminal, records)) = records.split_last() else {
+        return Err(SoundFontError::Malformed("missing sample headers"));
+    };
+
+    Ok(records
+        .iter()
+        .map(|record| SampleHeader {
+            name: name(record),
+            start: u32_at(record, 20).unwrap(),
+            end: u32_at(record, 24).unwrap(),
+            loop_start: u32_at(record, 28).unwrap(),
+            loop_end: u32_at(record, 32).unwrap(),
+            sample_rate: u32_at(record, 36).unwrap(),
+            original_pitch: record[40],
+            pitch_correction: record[41] as i8,
+            link: u16_at(record, 42).unwrap(),
+            kind: u16_at(record, 44).unwrap(),
+        })
+        .collect())
+}
+
+/// The zero padded name at the start of a record.
+fn name(record: &[u8]) -> String {
+    let name = &record[..20];
+    let length = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..length]).into_owned()
+}
+
+/// Copy the frames of one sample out of the sample data, with the loop of the header.
+fn sample_buffer(header: &SampleHeader, samples: &[f64]) -> SampleBuffer {
+    let start = (header.start as usize).min(samples.len());
+    let end = (header.end as usize).clamp(start, samples.len());
+    let frames = if header.kind & SAMPLE_TYPE_ROM == 0 {
+        samples[start..end].to_vec()
+    } else {
+        Vec::new()
+    };
+
+    let loop_start = (header.loop_start as usize).saturating_sub(start);
+    let loop_end = (header.loop_end as usize).saturating_sub(start);
+    SampleBuffer::new(frames, 1, header.sample_rate).with_loop(
+        LoopMode::OneShot,
+        loop_start,
+        loop_end,
+    )
+}
@@ -0,0 +1,424 @@
+use std::{error::Error, fmt, io, path::Path};
+
+use crate::instrument::midi_note_number_to_frequency;
+
+mod scala;
+
+pub use scala::{KeyboardMapping, Scale};
+
+/// The standard concert pitch of A4 (key 69).
+pub const CONCERT_PITCH: f64 = 440.0;
+
+const KEY_A4: u8 = 69;
+
+#[derive(Debug)]
+pub enum TuningError {
+    Io(io::Error),
+    /// A Scala file is broken, or its mapping leaves the reference key out.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TuningError::Io(e) => write!(f, "could not read tuning: {}", e),
+            TuningError::Malformed(reason) => write!(f, "malformed tuning: {}", reason),
+        }
+    }
+}
+
+impl Error for TuningError {}
+
+impl From<io::Error> for TuningError {
+    fn from(e: io::Error) -> Self {
+        TuningError::Io(e)
+    }
+}
+
+/// The frequency of every MIDI key. Keys without a frequency stay silent.
+///
+/// ```
+/// use rust_audio_shenanigans::tuning::{Tuning, KeyboardMapping, Scale};
+///
+/// // Baroque pitch.
+/// let baroque = Tuning::equal(415.0);
+/// // 19 equal steps to the octave, with A4 staying at 440 Hz.
+/// let edo19 = Tuning::edo(19, 440.0);
+/// // Just intonation in D.
+/// let just = Tuning::just(62, 440.0);
+/// // Bohlen-Pierce, which repeats at a tritave instead of an octave.
+/// let scale = Scale::from_ratios("Bohlen-Pierce", &[27.0 / 25.0, 25.0 / 21.0, 9.0 / 7.0,
+///     7.0 / 5.0, 75.0 / 49.0, 5.0 / 3.0, 9.0 / 5.0, 49.0 / 25.0, 15.0 / 7.0, 7.0 / 3.0,
+///     63.0 / 25.0, 25.0 / 9.0, 3.0]);
+/// let bp = Tuning::new(&scale, &KeyboardMapping::new(60, 60, 261.63)).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    frequencies: Vec<Option<f64>>,
+}
+
+impl Tuning {
+    /// Twelve-tone equal temperament with A4 at `reference` Hz.
+    pub fn equal(reference: f64) -> Self {
+        Self::edo(12, reference)
+    }
+
+    /// `divisions` equal steps to the octave, one per key, with A4 at `reference` Hz.
+    pub fn edo(divisions: usize, reference: f64) -> Self {
+        let mapping = KeyboardMapping::new(KEY_A4, KEY_A4, reference);
+        Self::tune(&Scale::edo(divisions), &mapping, 0.0)
+    }
+
+    /// 5-limit just intonation on the `tonic` key, which keeps its pitch from equal temperament
+    /// with A4 at `reference` Hz.
+    pub fn just(tonic: u8, reference: f64) -> Self {
+        let frequency = reference / CONCERT_PITCH * midi_note_number_to_frequency(tonic);
+        Self::tune(
+            &Scale::just(),
+            &KeyboardMapping::new(tonic, tonic, frequency),
+            0.0,
+        )
+    }
+
+    /// Lay `scale` out on the keys as `mapping` says. Fails if the scale is empty or the reference
+    /// key is not mapped.
+    pub fn new(scale: &Scale, mapping: &KeyboardMapping) -> Result<Self, TuningError> {
+        if scale.pitches.is_empty() {
+            return Err(TuningError::Malformed("empty scale"));
+        }
+        let reference = mapping
+            .pitch(scale, mapping.reference_key)
+            .ok_or(TuningError::Malformed("reference key is not mapped"))?;
+        Ok(Self::tune(scale, mapping, reference))
+    }
+
+    /// Load a Scala scale, mapped by a keyboard mapping file or, without one, to consecutive keys
+    /// from middle C on.
+    pub fn from_scala<P: AsRef<Path>>(scale: P, mapping: Option<P>) -> Result<Self, TuningError> {
+        let scale = Scale::from_file(scale)?;
+        let mapping = match mapping {
+            Some(mapping) => KeyboardMapping::from_file(mapping)?,
+            None => KeyboardMapping::new(60, 60, midi_note_number_to_frequency(60)),
+        };
+        Self::new(&scale, &mapping)
+    }
+
+    /// `reference` is the pitch of the reference key in cents above the tonic of the scale.
+    fn tune(scale: &Scale, mapping: &KeyboardMapping, reference: f64) -> Self {
+        let frequencies = (0..128)
+            .map(|key| {
+                let pitch = mapping.pitch(scale, key)?;
+                Some(mapping.reference_frequency * 2.0f64.powf((pitch - reference) / 1200.0))
+            })
+            .collect();
+        Self { frequencies }
+    }
+
+    /// The frequency of `key`, `None` if it is not mapped.
+    pub fn frequency(&self, key: usize) -> Option<f64> {
+        *self.frequencies.get(key.min(127))?
+    }
+
+    pub fn set_frequency(&mut self, key: u8, frequency: Option<f64>) {
+        if let Some(slot) = self.frequencies.get_mut(key as usize) {
+            *slot = frequency;
+        }
+    }
+
+    /// Apply the changes of a MIDI Tuning Standard message.
+    pub fn apply(&mut self, message: &TuningMessage) {
+        match message {
+            TuningMessage::Keys(keys) => {
+                for &(key, frequency) in keys {
+                    self.set_frequency(key, Some(frequency));
+                }
+            }
+            TuningMessage::Octave { cents, .. } => {
+                for key in 0..128u8 {
+                    let detune = 2.0f64.powf(cents[key as usize % 12] / 1200.0);
+                    self.set_frequency(key, Some(midi_note_number_to_frequency(key) * detune));
+                }
+            }
+        }
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(CONCERT_PITCH)
+    }
+}
+
+const SYSEX_NON_REAL_TIME: u8 = 0x7E;
+const SYSEX_REAL_TIME: u8 = 0x7F;
+const SYSEX_TUNING: u8 = 0x08;
+
+const TUNING_BULK_DUMP: u8 = 0x01;
+const TUNING_NOTE_CHANGE: u8 = 0x02;
+const TUNING_NOTE_CHANGE_BANK: u8 = 0x07;
+const TUNING_OCTAVE_1_BYTE: u8 = 0x08;
+const TUNING_OCTAVE_2_BYTE: u8 = 0x09;
+
+/// A MIDI Tuning Standard message. Tuning programs and banks are not kept apart, every message
+/// retunes the keys right away.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningMessage {
+    /// New frequencies for some keys, from a bulk dump or a note tuning change.
+    Keys(Vec<(u8, f64)>),
+    /// Detune of the twelve pitch classes from equal temperament in cents, starting at C, for the
+    /// channels in the bit mask (bit 0 is channel 1).
+    Octave { channels: u16, cents: [f64; 12] },
+}
+
+impl TuningMessage {
+    /// Parse a system exclusive message, with or without its leading 0xF0 and trailing 0xF7.
+    /// Messages that are not about tuning give `None`.
+    pub fn parse(sysex: &[u8]) -> Option<Self> {
+        let data = sysex.strip_prefix(&[0xF0]).unwrap_or(sysex);
+        let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+
+        let (&[universal, _device, SYSEX_TUNING, format], data) = data.split_first_chunk::<4>()?
+        else {
+            return None;
+        };
+        if universal != SYSEX_NON_REAL_TIME && universal != SYSEX_REAL_TIME {
+            return None;
+        }
+
+        match format {
+            // Program, 16 bytes of name, then all keys.
+            TUNING_BULK_DUMP => {
+                let keys = data.get(17..17 + 128 * 3)?;
+                let keys = keys
+                    .chunks_exact(3)
+                    .enumerate()
+                    .filter_map(|(key, bytes)| Some((key as u8, note_frequency(bytes)?)))
+                    .collect();
+                Some(TuningMessage::Keys(keys))
+            }
+            TUNING_NOTE_CHANGE => note_changes(data.get(1..)?),
+            TUNING_NOTE_CHANGE_BANK => note_changes(data.get(2..)?),
+            TUNING_OCTAVE_1_BYTE | TUNING_OCTAVE_2_BYTE => {
+                let channels = channel_mask(data.get(..3)?);
+                let mut cents = [0.0; 12];
+                let offsets = data.get(3..)?;
+                for (class, cent) in cents.iter_mut().enumerate() {
+                    *cent = if format == TUNING_OCTAVE_1_BYTE {
+                        *offsets.get(class)? as f64 - 64.0
+                    } else {
+                        let (msb, lsb) = (*offsets.get(class * 2)?, *offsets.get(class * 2 + 1)?);
+                        ((msb as f64 * 128.0 + lsb as f64) - 8192.0) / 8192.0 * 100.0
+                    };
+                }
+                Some(TuningMessage::Octave { channels, cents })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the message retunes `channel` (0 - 15).
+    pub fn applies_to(&self, channel: u8) -> bool {
+        match self {
+            TuningMessage::Keys(_) => true,
+            TuningMessage::Octave { channels, .. } => channels & (1 << channel.min(15)) != 0,
+        }
+    }
+}
+
+/// A count of key changes, each a key followed by its frequency.
+fn note_changes(data: &[u8]) -> Option<TuningMessage> {
+    let (&count, changes) = data.split_first()?;
+    let keys = changes
+        .chunks_exact(4)
+        .take(count as usize)
+        .filter_map(|change| Some((change[0] & 0x7F, note_frequency(&change[1..])?)))
+        .collect();
+    Some(TuningMessage::Keys(keys))
+}
+
+/// A frequency as a semitone and a 14 bit fraction of the next one. `7F 7F 7F` means no change.
+fn note_frequency(bytes: &[u8]) -> Option<f64> {
+    if bytes == [0x7F, 0x7F, 0x7F] {
+        return None;
+    }
+    let fraction = (bytes[1] as f64 * 128.0 + bytes[2] as f64) / 16384.0;
+    Some(midi_note_number_to_frequency(bytes[0] as f64 + fraction))
+}
+
+/// The channels of a scale/octave tuning message, three bytes of seven bits from channel 16 down.
+fn channel_mask(bytes: &[u8]) -> u16 {
+    let mask = (bytes[0] as u32 & 0x03) << 14 | (bytes[1] as u32 & 0x7F) << 7 | bytes[2] as u32;
+    mask as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn keys(message: Option<TuningMessage>) -> Vec<(u8, f64)> {
+        match message {
+            Some(TuningMessage::Keys(keys)) => keys,
+            other => panic!("not a key tuning: {:?}", other),
+        }
+    }
+
+    fn octave(message: Option<TuningMessage>) -> (u16, [f64; 12]) {
+        match message {
+            Some(TuningMessage::Octave { channels, cents }) => (channels, cents),
+            other => panic!("not an octave tuning: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bulk_dump_skips_unchanged_keys() {
+        let mut sysex = vec![0xF0, 0x7E, 0x7F, 0x08, 0x01, 0x00];
+        sysex.extend(b"Quarter tone C  ");
+        for key in 0..128u8 {
+            sysex.extend(match key {
+                // A quarter tone above C4.
+                60 => [60, 0x40, 0x00],
+                61 => [0x7F, 0x7F, 0x7F],
+                key => [key, 0x00, 0x00],
+            });
+        }
+        sysex.extend([0x00, 0xF7]);
+
+        let message = TuningMessage::parse(&sysex);
+        let keys = keys(message.clone());
+        assert_eq!(keys.len(), 127);
+        assert!(keys.iter().all(|&(key, _)| key != 61));
+        let (_, c4) = keys.iter().find(|&&(key, _)| key == 60).unwrap();
+        assert_close(*c4, midi_note_number_to_frequency(60.5));
+
+        let mut tuning = Tuning::equal(415.0);
+        tuning.apply(&message.unwrap());
+        assert_close(
+            tuning.frequency(60).unwrap(),
+            midi_note_number_to_frequency(60.5),
+        );
+        assert_close(
+            tuning.frequency(62).unwrap(),
+            midi_note_number_to_frequency(62),
+        );
+        // Left as it was.
+        assert_close(
+            tuning.frequency(61).unwrap(),
+            415.0 / CONCERT_PITCH * midi_note_number_to_frequency(61),
+        );
+    }
+
+    #[test]
+    fn single_note_change() {
+        // A4 retuned to a quarter of the way up to B flat, without the leading 0xF0.
+        let sysex = [0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 69, 0x20, 0x00, 0xF7];
+        let keys = keys(TuningMessage::parse(&sysex));
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].0, 69);
+        assert_close(keys[0].1, midi_note_number_to_frequency(69.25));
+    }
+
+    #[test]
+    fn one_byte_octave_tuning() {
+        // Channels 1 and 16, with C 10 cents sharp and B 64 cents flat.
+        let mut sysex = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x02, 0x00, 0x01];
+        sysex.extend([74, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 0]);
+        sysex.push(0xF7);
+
+        let message = TuningMessage::parse(&sysex);
+        let (channels, cents) = octave(message.clone());
+        assert_eq!(channels, 0x8001);
+        assert_eq!(cents[0], 10.0);
+        assert_eq!(cents[11], -64.0);
+        assert!(cents[1..11].iter().all(|&cent| cent == 0.0));
+
+        let message = message.unwrap();
+        assert!(message.applies_to(0));
+        assert!(message.applies_to(15));
+        assert!(!message.applies_to(1));
+    }
+
+    #[test]
+    fn two_byte_octave_tuning() {
+        // Channel 8, with E 50 cents flat.
+        let mut sysex = vec![0xF0, 0x7E, 0x7F, 0x08, 0x09, 0x00, 0x01, 0x00];
+        for class in 0..12 {
+            sysex.extend(if class == 4 {
+                [0x20, 0x00]
+            } else {
+                [0x40, 0x00]
+            });
+        }
+        sysex.push(0xF7);
+
+        let message = TuningMessage::parse(&sysex);
+        let (channels, cents) = octave(message.clone());
+        assert_eq!(channels, 0x0080);
+        assert_eq!(cents[4], -50.0);
+        assert_eq!(cents[5], 0.0);
+
+        let message = message.unwrap();
+        assert!(message.applies_to(7));
+        assert!(!message.applies_to(0));
+
+        let mut tuning = Tuning::default();
+        tuning.apply(&message);
+        assert_close(
+            tuning.frequency(64).unwrap(),
+            midi_note_number_to_frequency(63.5),
+        );
+    }
+
+    #[test]
+    fn scala_scale_with_unmapped_key() {
+        let scale = Scale::parse(
+            "! fifths.scl\n\
+             !\n\
+             Tonic, whole tone and fifth\n \
+             3\n\
+             !\n \
+             9/8\n \
+             701.955 cents\n \
+             2/1\n",
+        )
+        .unwrap();
+        let mapping = KeyboardMapping::parse(
+            "! fifths.kbm\n\
+             4 ! map size\n\
+             0\n\
+             127\n\
+             60 ! middle C is the tonic\n\
+             69 ! A4 is the whole tone\n\
+             440.0\n\
+             0\n\
+             ! mapping\n\
+             0\n\
+             1\n\
+             x\n\
+             2\n",
+        )
+        .unwrap();
+        let tuning = Tuning::new(&scale, &mapping).unwrap();
+
+        // A4 is the whole tone two periods above C4.
+        let tonic = 440.0 / 4.0 / (9.0 / 8.0);
+        assert_close(tuning.frequency(69).unwrap(), 440.0);
+        assert_close(tuning.frequency(60).unwrap(), tonic);
+        assert_close(tuning.frequency(61).unwrap(), tonic * 9.0 / 8.0);
+        assert_eq!(tuning.frequency(62), None);
+        assert_close(
+            tuning.frequency(63).unwrap(),
+            tonic * 2.0f64.powf(701.955 / 1200.0),
+        );
+        assert_close(tuning.frequency(64).unwrap(), tonic * 2.0);
+        assert_close(tuning.frequency(56).unwrap(), tonic / 2.0);
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::Path;
+
+use super::TuningError;
+
+/// Lines of a Scala file that carry data, without comments.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.starts_with('!'))
+}
+
+/// The first word of a line, values in Scala files may be followed by a comment.
+fn value(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn number<T: std::str::FromStr>(line: Option<&str>, what: &'static str) -> Result<T, TuningError> {
+    line.and_then(|line| value(line).parse().ok())
+        .ok_or(TuningError::Malformed(what))
+}
+
+/// A scale: the pitches of its degrees above the tonic, in cents. The last degree is the period
+/// the scale repeats at, usually the octave. A scale without any degrees can't be tuned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pub pitches: Vec<f64>,
+}
+
+impl Scale {
+    pub fn new(description: &str, pitches: Vec<f64>) -> Self {
+        Self {
+            description: description.to_string(),
+            pitches,
+        }
+    }
+
+    /// `divisions` equal steps to the octave.
+    pub fn edo(divisions: usize) -> Self {
+        let divisions = divisions.max(1);
+        let pitches = (1..=divisions)
+            .map(|step| 1200.0 * step as f64 / divisions as f64)
+            .collect();
+        Self::new(
+            &format!("{} equal divisions of the octave", divisions),
+            pitches,
+        )
+    }
+
+    /// A scale of frequency ratios to the tonic.
+    pub fn from_ratios(description: &str, ratios: &[f64]) -> Self {
+        let pitches = ratios.iter().map(|ratio| 1200.0 * ratio.log2()).collect();
+        Self::new(description, pitches)
+    }
+
+    /// Twelve notes in 5-limit just intonation.
+    pub fn just() -> Self {
+        Self::from_ratios(
+            "5-limit just intonation",
+            &[
+                16.0 / 15.0,
+                9.0 / 8.0,
+                6.0 / 5.0,
+                5.0 / 4.0,
+                4.0 / 3.0,
+                45.0 / 32.0,
+                3.0 / 2.0,
+                8.0 / 5.0,
+                5.0 / 3.0,
+                9.0 / 5.0,
+                15.0 / 8.0,
+                2.0,
+            ],
+        )
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TuningError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a Scala scale (.scl). Pitches with a period are in cents, all others are ratios like
+    /// `3/2` or `2`.
+    pub fn parse(text: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(text);
+        let description = lines.next().ok_or(TuningError::Malformed("empty scale"))?;
+        let count: usize = number(lines.next(), "missing number of notes")?;
+
+        let pitches = lines
+            .filter(|line| !line.trim().is_empty())
+            .take(count)
+            .map(|line| pitch(value(line)).ok_or(TuningError::Malformed("invalid pitch")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if pitches.len() != count || count == 0 {
+            return Err(TuningError::Malformed("wrong number of notes"));
+        }
+
+        Ok(Self::new(description.trim(), pitches))
+    }
+
+    /// The pitch of `degree` above the tonic, counting on into the following periods. The scale
+    /// must not be empty.
+    pub(super) fn degree(&self, degree: i64) -> f64 {
+        let size = self.pitches.len() as i64;
+        let period = self.pitches[self.pitches.len() - 1];
+        let (periods, step) = (degree.div_euclid(size), degree.rem_euclid(size));
+        let pitch = match step {
+            0 => 0.0,
+            step => self.pitches[step as usize - 1],
+        };
+        periods as f64 * period + pitch
+    }
+}
+
+/// A pitch of a scale file in cents.
+fn pitch(value: &str) -> Option<f64> {
+    if value.contains('.') {
+        return value.parse().ok();
+    }
+    let ratio = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?
+        }
+        None => value.parse().ok()?,
+    };
+    (ratio > 0.0).then(|| 1200.0 * ratio.log2())
+}
+
+/// How the degrees of a [`Scale`] are laid out on the keys, as in a Scala keyboard mapping (.kbm).
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// First and last key that is tuned, keys outside stay silent.
+    pub first: u8,
+    pub last: u8,
+    /// Key the tonic of the scale is mapped to.
+    pub middle: u8,
+    /// Key that sounds at `reference_frequency`.
+    pub reference_key: u8,
+    pub reference_frequency: f64,
+    /// Scale degree the pattern repeats at, 0 for the period of the scale.
+    pub octave_degree: usize,
+    /// Scale degrees of the keys from the middle key on, repeating. `None` leaves a key silent. An
+    /// empty pattern maps consecutive keys to consecutive degrees.
+    pub pattern: Vec<Option<usize>>,
+}
+
+impl KeyboardMapping {
+    /// Maps consecutive keys to consecutive degrees, with the tonic on `middle`.
+    pub fn new(middle: u8, reference_key: u8, reference_frequency: f64) -> Self {
+        Self {
+            first: 0,
+            last: 127,
+            middle,
+            reference_key,
+            reference_frequency,
+            octave_degree: 0,
+            pattern: Vec::new(),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TuningError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a Scala keyboard mapping (.kbm).
+    pub fn parse(text: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(text).filter(|line| !line.trim().is_empty());
+        let size: usize = number(lines.next(), "missing map size")?;
+        let first = number(lines.next(), "missing first note")?;
+        let last = number(lines.next(), "missing last note")?;
+        let middle = number(lines.next(), "missing middle note")?;
+        let reference_key = number(lines.next(), "missing reference note")?;
+        let reference_frequency = number(lines.next(), "missing reference frequency")?;
+        let octave_degree = number(lines.next(), "missing octave degree")?;
+
+        let pattern = lines
+            .take(size)
+            .map(|line| match value(line) {
+                "x" | "X" => Ok(None),
+                degree => degree
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| TuningError::Malformed("invalid mapping")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Mappings may leave out trailing keys, which are silent then.
+        let pattern = pattern
+            .into_iter()
+            .chain(std::iter::repeat(None))
+            .take(size)
+            .collect();
+
+        Ok(Self {
+            first,
+            last,
+            middle,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            pattern,
+        })
+    }
+
+    /// The pitch of `key` in cents above the tonic of `scale`, if the key is mapped.
+    pub(super) fn pitch(&self, scale: &Scale, key: u8) -> Option<f64> {
+        if key < self.first || key > self.last {
+            return None;
+        }
+        let offset = key as i64 - self.middle as i64;
+        if self.pattern.is_empty() {
+            return Some(scale.degree(offset));
+        }
+
+        let size = self.pattern.len() as i64;
+        let octave = match self.octave_degree {
+            0 => scale.degree(scale.pitches.len() as i64),
+            degree => scale.degree(degree as i64),
+        };
+        let degree = self.pattern[offset.rem_euclid(size) as usize]?;
+        Some(offset.div_euclid(size) as f64 * octave + scale.degree(degree as i64))
+    }
+}
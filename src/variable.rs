@@ -29,8 +29,8 @@ where
         let lock_value = Arc::new(RwLock::new(value.clone()));
         (
             Self::Dynamic {
-                value: value.clone(),
-                lock_value: Arc::new(RwLock::new(value)).clone(),
+                value,
+                lock_value: lock_value.clone(),
             },
             lock_value,
         )
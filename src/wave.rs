@@ -2,6 +2,14 @@ use std::ops::Deref;
 
 pub trait Wave {
     fn next_sample(&mut self) -> f64;
+
+    /// Left and right channel of the next sample. Mono waves play the same sample on both
+    /// channels. Stereo waves return the average of both channels from `next_sample`.
+    fn next_frame(&mut self) -> (f64, f64) {
+        let sample = self.next_sample();
+        (sample, sample)
+    }
+
     fn sample_rate(&self) -> u32 {
         44100
     }
@@ -25,6 +33,23 @@ where
     fn next_sample(&mut self) -> f64 {
         self.source.next_sample()
     }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.source.next_frame()
+    }
+}
+
+impl<W> Wave for Box<W>
+where
+    W: Wave + ?Sized,
+{
+    fn next_sample(&mut self) -> f64 {
+        (**self).next_sample()
+    }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        (**self).next_frame()
+    }
 }
 
 impl<T> From<T> for WaveGenerator<T> {
@@ -9,10 +9,15 @@ mod adsr;
 mod constant;
 pub mod misc;
 mod mix;
+mod physical;
 
 pub use adsr::{ADSREvent, Trigger as ADSRTrigger, ADSR};
 pub use constant::{Constant, VariableConstant};
-pub use mix::WaveMixer;
+pub use mix::{MixAdd, MixDiv, MixFn, MixMul, MixSub, WaveMixer};
+pub use physical::{
+    blown, bowed, pluck, Bow, Exciter, KarplusStrong, PartialKarplusStrong, PartialWaveguide, Reed,
+    Waveguide,
+};
 
 use self::misc::PartialPass;
 
@@ -1,5 +1,5 @@
 use std::sync::{
-    atomic::{AtomicU32, Ordering},
+    atomic::{AtomicBool, AtomicU32, Ordering},
     Arc,
 };
 
@@ -17,11 +17,20 @@ const VEL_MASK: u32 = 0x7F;
 
 pub struct Trigger {
     _trigger: Arc<AtomicU32>,
+    idle: Arc<AtomicBool>,
 }
 
 impl Trigger {
-    pub fn new(trigger: Arc<AtomicU32>) -> Self {
-        Self { _trigger: trigger }
+    pub fn new(trigger: Arc<AtomicU32>, idle: Arc<AtomicBool>) -> Self {
+        Self {
+            _trigger: trigger,
+            idle,
+        }
+    }
+
+    /// Whether the envelope has finished its release and only outputs silence.
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Relaxed)
     }
 
     pub fn trigger(&self, e: ADSREvent) {
@@ -45,6 +54,7 @@ pub struct ADSR {
     level: f64,
     hold: bool,
     trigger: Arc<AtomicU32>,
+    idle: Arc<AtomicBool>,
 }
 
 impl ADSR {
@@ -55,6 +65,7 @@ impl ADSR {
         release: f64,
     ) -> (WaveGenerator<Self>, Trigger) {
         let trigger = Arc::new(AtomicU32::new(0));
+        let idle = Arc::new(AtomicBool::new(true));
         (
             Self {
                 attack,
@@ -65,9 +76,10 @@ impl ADSR {
                 level: 0.0,
                 hold: false,
                 trigger: trigger.clone(),
+                idle: idle.clone(),
             }
             .into(),
-            Trigger::new(trigger),
+            Trigger::new(trigger, idle),
         )
     }
 
@@ -109,6 +121,7 @@ impl ADSR {
                 };
                 self.level = vel as f64 / 127.0;
                 self.hold = true;
+                self.idle.store(false, Ordering::Relaxed);
             }
         }
     }
@@ -119,6 +132,7 @@ impl Wave for ADSR {
         self.handle_msg();
 
         if self.phase >= self.attack + self.decay + self.release {
+            self.idle.store(true, Ordering::Relaxed);
             return 0.0;
         }
 
@@ -27,12 +27,24 @@ impl<W: Wave> Wave for Vec<W> {
     fn next_sample(&mut self) -> f64 {
         self.iter_mut().map(|w| w.next_sample()).sum()
     }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.iter_mut()
+            .map(|w| w.next_frame())
+            .fold((0.0, 0.0), |(l, r), (wl, wr)| (l + wl, r + wr))
+    }
 }
 
 impl<W: Wave, K> Wave for HashMap<K, W> {
     fn next_sample(&mut self) -> f64 {
         self.values_mut().map(|w| w.next_sample()).sum()
     }
+
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.values_mut()
+            .map(|w| w.next_frame())
+            .fold((0.0, 0.0), |(l, r), (wl, wr)| (l + wl, r + wr))
+    }
 }
 
 #[derive(Clone)]
@@ -51,6 +63,11 @@ impl<W: Wave> Wave for Pass<W> {
     fn next_sample(&mut self) -> f64 {
         self.input.next_sample()
     }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        self.input.next_frame()
+    }
 }
 
 make_partial!(PartialPass {} => Pass);
@@ -52,6 +52,16 @@ where
         self.mixer
             .mix(self.left.next_sample(), self.right.next_sample())
     }
+
+    #[inline]
+    fn next_frame(&mut self) -> (f64, f64) {
+        let (left_l, left_r) = self.left.next_frame();
+        let (right_l, right_r) = self.right.next_frame();
+        (
+            self.mixer.mix(left_l, right_l),
+            self.mixer.mix(left_r, right_r),
+        )
+    }
 }
 
 macro_rules! generator_op {
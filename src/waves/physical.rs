@@ -0,0 +1,247 @@
+use crate::{
+    make_partial,
+    partial_wave::{PartialWave, PartialWaveBuilder},
+    random::Random,
+    wave::{Wave, WaveGenerator},
+};
+
+/// Length of the delay lines, enough for notes down to ~11Hz.
+const MAX_DELAY: usize = 4096;
+
+/// Loss per round trip of a plucked string, on top of the damping filter.
+const PLUCK_LOOP_GAIN: f64 = 0.9995;
+
+/// Reflection at the open end of a bore or the bridge of a string. The sign flips the wave.
+const WAVEGUIDE_REFLECTION: f64 = -0.95;
+
+#[derive(Clone)]
+struct DelayLine {
+    buffer: Vec<f64>,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new() -> Self {
+        Self {
+            buffer: vec![0.0; MAX_DELAY],
+            position: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, x: f64) {
+        self.position = (self.position + 1) % MAX_DELAY;
+        self.buffer[self.position] = x;
+    }
+
+    /// The sample pushed `delay` samples ago, interpolating linearly. `read(1.0)` is the latest.
+    #[inline]
+    fn read(&self, delay: f64) -> f64 {
+        let delay = delay - 1.0;
+        let whole = delay as usize;
+        let fraction = delay - whole as f64;
+        let a = self.buffer[(self.position + MAX_DELAY - whole) % MAX_DELAY];
+        let b = self.buffer[(self.position + MAX_DELAY - whole - 1) % MAX_DELAY];
+        a + (b - a) * fraction
+    }
+}
+
+/// Delay in samples for one period of `frequency`, minus the delay the loop adds on its own.
+#[inline]
+fn period(frequency: f64, loop_delay: f64) -> f64 {
+    (44100.0 / frequency.abs().max(1.0) - loop_delay).clamp(2.0, (MAX_DELAY - 2) as f64)
+}
+
+/// Karplus-Strong plucked string. The input is the frequency of the note.
+///
+/// A burst of noise is plucked into a delay line one period long when the first sample is
+/// requested, and fades while it circles through the line. `brightness` (0.0 - 1.0) filters the
+/// noise burst, lower values sound like a softer pluck. `damping` (0.0 - 1.0) filters the string on
+/// every round trip, higher values let the overtones and the note die away sooner.
+#[derive(Clone)]
+pub struct KarplusStrong<T> {
+    damping: f64,
+    brightness: f64,
+    line: DelayLine,
+    plucked: bool,
+    input: T,
+}
+
+impl<T> KarplusStrong<T> {
+    pub fn new(damping: f64, brightness: f64, input: T) -> WaveGenerator<Self> {
+        Self {
+            damping: damping.clamp(0.0, 1.0),
+            brightness: brightness.clamp(0.01, 1.0),
+            line: DelayLine::new(),
+            plucked: false,
+            input,
+        }
+        .into()
+    }
+
+    fn pluck(&mut self, length: usize) {
+        let mut random = Random::new();
+        let mut lowpassed = 0.0;
+        let burst: Vec<f64> = (0..length)
+            .map(|_| {
+                lowpassed += self.brightness * (random.bipolar() - lowpassed);
+                lowpassed
+            })
+            .collect();
+
+        // The loop keeps any offset forever, so the burst is centered around zero.
+        let mean = burst.iter().sum::<f64>() / length as f64;
+        for x in burst {
+            self.line.push(x - mean);
+        }
+    }
+}
+
+impl<W: Wave> Wave for KarplusStrong<W> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        // The damping filter averages two neighbouring samples, which delays the loop a bit.
+        let blend = self.damping / 2.0;
+        let delay = period(self.input.next_sample(), blend);
+        if !self.plucked {
+            self.pluck(delay.ceil() as usize);
+            self.plucked = true;
+        }
+
+        let out = self.line.read(delay);
+        let next = self.line.read(delay + 1.0);
+        self.line
+            .push(PLUCK_LOOP_GAIN * ((1.0 - blend) * out + blend * next));
+        out
+    }
+}
+
+make_partial!(PartialKarplusStrong { damping: f64, brightness: f64 } => KarplusStrong);
+
+/// Plucked string, e.g. `constant(110) >> pluck(0.5, 0.8)` for a bright guitar-like pluck.
+pub fn pluck(damping: f64, brightness: f64) -> PartialWaveBuilder<PartialKarplusStrong> {
+    PartialKarplusStrong::new(damping, brightness)
+}
+
+/// Drives a `Waveguide`. Given the wave reflected back to the excitation point and a random value
+/// between -1.0 and 1.0 for noise, an exciter decides what goes back into the delay line.
+pub trait Exciter {
+    fn excite(&mut self, reflection: f64, noise: f64) -> f64;
+}
+
+/// A reed blown with constant `pressure` (0.0 - 1.0) into a bore that is closed at one end, like
+/// a clarinet. The reed only starts to sound above a pressure of about 0.6. `noise` adds breath
+/// noise to the pressure.
+#[derive(Clone)]
+pub struct Reed {
+    pub pressure: f64,
+    pub noise: f64,
+}
+
+impl Exciter for Reed {
+    #[inline]
+    fn excite(&mut self, reflection: f64, noise: f64) -> f64 {
+        let breath = self.pressure * (1.0 + self.noise * noise);
+        let difference = reflection - breath;
+        // The reed closes when the pressure inside the mouth rises above the pressure in the bore.
+        let reed = (0.7 - 0.3 * difference).clamp(-1.0, 1.0);
+        breath + difference * reed
+    }
+}
+
+/// A string bowed with `pressure` (0.0 - 1.0) at a constant `speed` (0.0 - 1.0), like a violin.
+#[derive(Clone)]
+pub struct Bow {
+    pub pressure: f64,
+    pub speed: f64,
+}
+
+impl Exciter for Bow {
+    #[inline]
+    fn excite(&mut self, reflection: f64, _noise: f64) -> f64 {
+        let velocity = 0.03 + 0.2 * self.speed;
+        let difference = velocity - reflection;
+        // The string sticks to the bow while their velocities are close and slips otherwise.
+        let slope = 5.0 - 4.0 * self.pressure;
+        let friction = ((difference * slope).abs() + 0.75)
+            .powi(-4)
+            .clamp(0.01, 0.98);
+        reflection + difference * friction
+    }
+}
+
+/// Digital waveguide: a delay line half a period long that is terminated by a lowpass reflection on
+/// one end and driven by an [`Exciter`] on the other. Unlike a plucked string, the exciter keeps
+/// feeding the waveguide, so the note sustains as long as it is held. The input is the frequency
+/// of the note.
+#[derive(Clone)]
+pub struct Waveguide<T, E> {
+    exciter: E,
+    line: DelayLine,
+    last: f64,
+    random: Random,
+    input: T,
+}
+
+impl<T, E> Waveguide<T, E> {
+    pub fn new(exciter: E, input: T) -> WaveGenerator<Self> {
+        Self {
+            exciter,
+            line: DelayLine::new(),
+            last: 0.0,
+            random: Random::new(),
+            input,
+        }
+        .into()
+    }
+}
+
+impl<W: Wave, E: Exciter> Wave for Waveguide<W, E> {
+    #[inline]
+    fn next_sample(&mut self) -> f64 {
+        // The reflection flips the wave, so it takes two trips through the line to complete one
+        // period. Averaging two samples in the reflection delays each trip by half a sample.
+        let delay = period(self.input.next_sample() * 2.0, 0.5);
+
+        let out = self.line.read(delay);
+        let reflection = WAVEGUIDE_REFLECTION * (out + self.last) / 2.0;
+        self.last = out;
+
+        let x = self.exciter.excite(reflection, self.random.bipolar());
+        self.line.push(x);
+        out
+    }
+}
+
+#[derive(Clone)]
+pub struct PartialWaveguide<E> {
+    exciter: E,
+}
+
+impl<E> PartialWave for PartialWaveguide<E>
+where
+    E: Exciter + Clone + Send + Sync,
+{
+    type Target<W: Wave + Clone + Send + Sync> = Waveguide<W, E>;
+
+    fn build<W>(self, input: W) -> WaveGenerator<Self::Target<W>>
+    where
+        W: Wave + Clone + Send + Sync,
+    {
+        Waveguide::new(self.exciter, input)
+    }
+}
+
+/// Blown reed instrument, e.g. `constant(220) >> blown(0.8, 0.1)`.
+pub fn blown(pressure: f64, noise: f64) -> PartialWaveBuilder<PartialWaveguide<Reed>> {
+    PartialWaveBuilder::new(PartialWaveguide {
+        exciter: Reed { pressure, noise },
+    })
+}
+
+/// Bowed string, e.g. `constant(440) >> bowed(0.5, 0.8)`.
+pub fn bowed(pressure: f64, speed: f64) -> PartialWaveBuilder<PartialWaveguide<Bow>> {
+    PartialWaveBuilder::new(PartialWaveguide {
+        exciter: Bow { pressure, speed },
+    })
+}